@@ -0,0 +1,80 @@
+//--------------------------------    Struct Aabb    -------------------------------------
+// Axis aligned bounding box.
+use crate::graphics::ray::Ray;
+use crate::math_support::*;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn make_aabb(a: Vec3, b: Vec3) -> Aabb {
+        Aabb {
+            min: Vec3::make_vec3(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Vec3::make_vec3(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    pub fn surround(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::make_vec3(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Vec3::make_vec3(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn get_centre(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn get_diagonal(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn get_longest_axis(&self) -> usize {
+        let d = self.get_diagonal();
+        if d.x >= d.y && d.x >= d.z {
+            0
+        } else if d.y >= d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn contains(&self, pos: Vec3) -> bool {
+        pos.x >= self.min.x
+            && pos.y >= self.min.y
+            && pos.z >= self.min.z
+            && pos.x <= self.max.x
+            && pos.y <= self.max.y
+            && pos.z <= self.max.z
+    }
+
+    pub fn get_hit_range(&self, target_ray: &Ray) -> Option<(f64, f64)> {
+        // Slab test, the times the ray enters and leaves the box.
+        let (mut t0, mut t1) = (f64::NEG_INFINITY, f64::INFINITY);
+        for axis in 0..3 {
+            let inv = 1.0 / target_ray.get_dir().get(axis);
+            let origin = target_ray.get_pos().get(axis);
+            let ta = (self.min.get(axis) - origin) * inv;
+            let tb = (self.max.get(axis) - origin) * inv;
+            t0 = t0.max(ta.min(tb));
+            t1 = t1.min(ta.max(tb));
+        }
+        if t0 > t1 || t1 < 0.0 {
+            None
+        } else {
+            Some((t0, t1))
+        }
+    }
+}
@@ -0,0 +1,379 @@
+//--------------------------------    Mod Bezier    --------------------------------------
+// Bicubic Bezier patches, intersected directly. The ray is the meet of two planes, and
+// Newton's method finds the (u, v) on both, started in every cell of a grid over the
+// patch whose box the ray passes. Normals are exact, from the derivatives of the patch.
+use crate::entity::aabb::Aabb;
+use crate::entity::material::*;
+use crate::entity::CanHit;
+use crate::graphics::ray::Ray;
+use crate::math_support::*;
+use std::fs;
+
+const CELLS: usize = 6; // Per side of the grid over a patch.
+const NEWTON_STEPS: i32 = 10;
+const NEWTON_TOL: f64 = 0.0000001;
+const MIN_TIME: f64 = 0.00001; // Rays leaving a patch do not hit it again nearer.
+const CIRCLE_K: f64 = 0.552_284_749_8; // Cubic approximation of a quarter circle.
+
+fn get_bernstein(t: f64) -> ([f64; 4], [f64; 4]) {
+    // Cubic basis and its derivative.
+    let s = 1.0 - t;
+    (
+        [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t],
+        [
+            -3.0 * s * s,
+            3.0 * s * s - 6.0 * t * s,
+            6.0 * t * s - 3.0 * t * t,
+            3.0 * t * t,
+        ],
+    )
+}
+
+fn split_curve(p: [Vec3; 4], t: f64) -> ([Vec3; 4], [Vec3; 4]) {
+    // De Casteljau, the curve before t and after it.
+    let lerp = |a: Vec3, b: Vec3| (1.0 - t) * a + t * b;
+    let (p01, p12, p23) = (lerp(p[0], p[1]), lerp(p[1], p[2]), lerp(p[2], p[3]));
+    let (p012, p123) = (lerp(p01, p12), lerp(p12, p23));
+    let mid = lerp(p012, p123);
+    ([p[0], p01, p012, mid], [mid, p123, p23, p[3]])
+}
+
+fn get_segment(p: [Vec3; 4], t0: f64, t1: f64) -> [Vec3; 4] {
+    // Control points of the curve from t0 to t1.
+    let after = split_curve(p, t0).1;
+    if t0 >= 1.0 {
+        return after;
+    }
+    split_curve(after, (t1 - t0) / (1.0 - t0)).0
+}
+
+fn get_points_box(points: &[Vec3]) -> Aabb {
+    points
+        .iter()
+        .fold(Aabb::make_aabb(points[0], points[0]), |res, p| {
+            res.surround(&Aabb::make_aabb(*p, *p))
+        })
+}
+
+//--------------------------------    Struct Patch    ------------------------------------
+
+pub struct Patch {
+    cp: [Vec3; 16], // Row by row, u along a row and v across the rows.
+    bbox: Aabb,
+    cells: Vec<(Aabb, f64, f64)>, // Box and the uv of the centre, of every cell.
+}
+
+impl Patch {
+    pub fn make_patch(cp: [Vec3; 16]) -> Patch {
+        // The box of the control points of a cell bounds it.
+        let step = 1.0 / CELLS as f64;
+        let mut cells = Vec::with_capacity(CELLS * CELLS);
+        for i in 0..CELLS {
+            let (v0, v1) = (i as f64 * step, (i + 1) as f64 * step);
+            for j in 0..CELLS {
+                let (u0, u1) = (j as f64 * step, (j + 1) as f64 * step);
+                let rows: Vec<[Vec3; 4]> = (0..4)
+                    .map(|row| {
+                        let p = [
+                            cp[4 * row],
+                            cp[4 * row + 1],
+                            cp[4 * row + 2],
+                            cp[4 * row + 3],
+                        ];
+                        get_segment(p, u0, u1)
+                    })
+                    .collect();
+                let points: Vec<Vec3> = (0..4)
+                    .flat_map(|col| {
+                        let p = [rows[0][col], rows[1][col], rows[2][col], rows[3][col]];
+                        get_segment(p, v0, v1)
+                    })
+                    .collect();
+                cells.push((get_points_box(&points), (u0 + u1) / 2.0, (v0 + v1) / 2.0));
+            }
+        }
+        Patch {
+            cp,
+            bbox: get_points_box(&cp),
+            cells,
+        }
+    }
+
+    fn get_point(&self, u: f64, v: f64) -> (Vec3, Vec3, Vec3) {
+        // The point and its derivatives along u and v.
+        let (bu, dbu) = get_bernstein(u);
+        let (bv, dbv) = get_bernstein(v);
+        let zero = Vec3::make_vec3(0.0, 0.0, 0.0);
+        let (mut p, mut du, mut dv) = (zero, zero, zero);
+        for i in 0..4 {
+            for j in 0..4 {
+                let cp = self.cp[4 * i + j];
+                p = p + (bv[i] * bu[j]) * cp;
+                du = du + (bv[i] * dbu[j]) * cp;
+                dv = dv + (dbv[i] * bu[j]) * cp;
+            }
+        }
+        (p, du, dv)
+    }
+
+    fn get_normal(&self, u: f64, v: f64) -> Vec3 {
+        let (_, du, dv) = self.get_point(u, v);
+        let normal = cross(du, dv);
+        if normal.get_len() < EPS {
+            // A collapsed edge, like the top of a lid. Nudged towards the middle.
+            let (_, du, dv) = self.get_point(0.5 + 0.999 * (u - 0.5), 0.5 + 0.999 * (v - 0.5));
+            let normal = cross(du, dv);
+            if normal.get_len() < EPS {
+                // Collapsed to a line or a point.
+                return Vec3::make_vec3(0.0, 1.0, 0.0);
+            }
+            return normal.normalize();
+        }
+        normal.normalize()
+    }
+
+    fn get_hit_time(&self, target_ray: &Ray, before: f64) -> Option<f64> {
+        // The earliest hit from MIN_TIME to before.
+        let (o, d) = (target_ray.get_pos(), target_ray.get_dir());
+        let (n1, n2) = make_onb(d);
+        let mut res: Option<f64> = None;
+        for (bbox, u0, v0) in &self.cells {
+            let nearest = res.unwrap_or(before);
+            match bbox.get_hit_range(target_ray) {
+                Some((t0, t1)) if t0 < nearest && t1 >= MIN_TIME => {}
+                _ => continue,
+            }
+            let (mut u, mut v) = (*u0, *v0);
+            let mut converged = false;
+            for _ in 0..NEWTON_STEPS {
+                let (p, du, dv) = self.get_point(u, v);
+                let (f1, f2) = (dot(n1, p - o), dot(n2, p - o));
+                if f1.abs() < NEWTON_TOL && f2.abs() < NEWTON_TOL {
+                    converged = true;
+                    break;
+                }
+                let (a, b, c, e) = (dot(n1, du), dot(n1, dv), dot(n2, du), dot(n2, dv));
+                let det = a * e - b * c;
+                if det.abs() < EPS * EPS {
+                    break;
+                }
+                u += (-f1 * e + f2 * b) / det;
+                v += (-f2 * a + f1 * c) / det;
+                if !(-0.5..=1.5).contains(&u) || !(-0.5..=1.5).contains(&v) {
+                    break;
+                }
+            }
+            if !converged || !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+                continue;
+            }
+            let tm = dot(self.get_point(u, v).0 - o, d);
+            if tm >= MIN_TIME && tm < nearest {
+                res = Some(tm);
+            }
+        }
+        res
+    }
+
+    fn get_closest(&self, pos: Vec3) -> (f64, f64, f64) {
+        // Distance to the nearest point of the patch, and its uv, by Gauss-Newton
+        // started in the cells near pos.
+        let margin = Vec3::make_vec3(MIN_TIME, MIN_TIME, MIN_TIME);
+        let mut res = (f64::INFINITY, 0.5, 0.5);
+        for (bbox, u0, v0) in &self.cells {
+            if !Aabb::make_aabb(bbox.min - margin, bbox.max + margin).contains(pos) {
+                continue;
+            }
+            let (mut u, mut v) = (*u0, *v0);
+            for _ in 0..NEWTON_STEPS {
+                let (p, du, dv) = self.get_point(u, v);
+                let r = pos - p;
+                let (a, b, e) = (dot(du, du), dot(du, dv), dot(dv, dv));
+                let det = a * e - b * b;
+                if det.abs() < EPS * EPS {
+                    break;
+                }
+                let (ru, rv) = (dot(du, r), dot(dv, r));
+                u = (u + (ru * e - rv * b) / det).clamp(0.0, 1.0);
+                v = (v + (rv * a - ru * b) / det).clamp(0.0, 1.0);
+            }
+            let dist = (pos - self.get_point(u, v).0).get_len();
+            if dist < res.0 {
+                res = (dist, u, v);
+            }
+        }
+        res
+    }
+}
+
+//------------------------------    Struct BezierMesh    ---------------------------------
+
+pub struct BezierMesh {
+    patches: Vec<Patch>,
+    bbox: Aabb,
+    pub material: Mat,
+}
+
+impl BezierMesh {
+    pub fn make_bezier_mesh(patches: Vec<[Vec3; 16]>, material: Mat) -> Result<BezierMesh, String> {
+        let patches: Vec<Patch> = patches.into_iter().map(Patch::make_patch).collect();
+        let bbox = patches
+            .iter()
+            .map(|patch| patch.bbox)
+            .reduce(|res, bbox| res.surround(&bbox))
+            .ok_or_else(|| "a bezier mesh needs patches".to_string())?;
+        Ok(BezierMesh {
+            patches,
+            bbox,
+            material,
+        })
+    }
+
+    pub fn load(path: &str, pos: Vec3, scale: f64, material: Mat) -> Result<BezierMesh, String> {
+        // The format of the Utah teapot: the number of patches, 16 vertex indices from 1
+        // for each, the number of vertices, then x, y, z of each. Commas or spaces apart.
+        // Its z is up, which is turned to y. The origin of the file is put at pos.
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let mut tokens = text
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty());
+        let mut next = || -> Result<f64, String> {
+            let token = tokens
+                .next()
+                .ok_or_else(|| format!("{} is truncated", path))?;
+            token
+                .parse::<f64>()
+                .map_err(|_| format!("{} has a bad number {}", path, token))
+        };
+
+        let mut next_count = || -> Result<usize, String> {
+            // Not trusted for allocating, the vectors grow as the tokens are read.
+            let val = next()?;
+            if val < 0.0 || val.fract() != 0.0 || val > u32::MAX as f64 {
+                return Err(format!("{} has a bad count {}", path, val));
+            }
+            Ok(val as usize)
+        };
+
+        let patch_num = next_count()?;
+        let mut indices = Vec::new();
+        let index_num = patch_num
+            .checked_mul(16)
+            .ok_or_else(|| format!("{} has a bad count {}", path, patch_num))?;
+        for _ in 0..index_num {
+            indices.push(next_count()?);
+        }
+        let vertex_num = next_count()?;
+        let mut vertices = Vec::new();
+        for _ in 0..vertex_num {
+            let (x, y, z) = (next()?, next()?, next()?);
+            vertices.push(pos + scale * Vec3::make_vec3(x, z, -y));
+        }
+
+        let mut patches = Vec::new();
+        for patch in indices.chunks(16) {
+            let mut cp = [Vec3::make_vec3(0.0, 0.0, 0.0); 16];
+            for (k, id) in patch.iter().enumerate() {
+                if *id == 0 || *id > vertex_num {
+                    return Err(format!("{} has a bad vertex index {}", path, id));
+                }
+                cp[k] = vertices[id - 1];
+            }
+            patches.push(cp);
+        }
+        BezierMesh::make_bezier_mesh(patches, material).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    pub fn make_revolution(
+        pos: Vec3,
+        profile: &[(f64, f64)],
+        material: Mat,
+    ) -> Result<BezierMesh, String> {
+        // Turns a profile of cubic segments, (radius, height) of 3 k + 1 points, around
+        // the y axis through pos. Each segment makes four patches, one per quarter. A profile
+        // of less than 4 points has no segment.
+        let mut patches = Vec::new();
+        for segment in profile.windows(4).step_by(3) {
+            for quarter in 0..4 {
+                let a0 = quarter as f64 * std::f64::consts::FRAC_PI_2;
+                let a1 = a0 + std::f64::consts::FRAC_PI_2;
+                let (c0, c1) = (
+                    Vec3::make_vec3(a0.cos(), 0.0, a0.sin()),
+                    Vec3::make_vec3(a1.cos(), 0.0, a1.sin()),
+                );
+                let arc = [
+                    c0,
+                    c0 + CIRCLE_K * Vec3::make_vec3(-a0.sin(), 0.0, a0.cos()),
+                    c1 + CIRCLE_K * Vec3::make_vec3(a1.sin(), 0.0, -a1.cos()),
+                    c1,
+                ];
+                let mut cp = [Vec3::make_vec3(0.0, 0.0, 0.0); 16];
+                for (i, (r, y)) in segment.iter().enumerate() {
+                    for (j, a) in arc.iter().enumerate() {
+                        cp[4 * i + j] = pos + *r * *a + Vec3::make_vec3(0.0, *y, 0.0);
+                    }
+                }
+                patches.push(cp);
+            }
+        }
+        BezierMesh::make_bezier_mesh(patches, material)
+    }
+
+    fn get_closest(&self, pos: Vec3) -> (&Patch, f64, f64) {
+        // The patch pos is on, and the uv there.
+        let mut res = (&self.patches[0], f64::INFINITY, 0.5, 0.5);
+        for patch in &self.patches {
+            let (dist, u, v) = patch.get_closest(pos);
+            if dist < res.1 {
+                res = (patch, dist, u, v);
+            }
+        }
+        (res.0, res.2, res.3)
+    }
+}
+
+impl CanHit for BezierMesh {
+    fn get_hit_time(&self, target_ray: &Ray) -> f64 {
+        if self.bbox.get_hit_range(target_ray).is_none() {
+            return -1.0;
+        }
+        let mut res: Option<f64> = None;
+        for patch in &self.patches {
+            if patch.bbox.get_hit_range(target_ray).is_none() {
+                continue;
+            }
+            if let Some(tm) = patch.get_hit_time(target_ray, res.unwrap_or(f64::INFINITY)) {
+                res = Some(tm);
+            }
+        }
+        res.unwrap_or(-1.0)
+    }
+
+    fn get_hit_normal(&self, pos: Vec3) -> Vec3 {
+        self.get_surface(pos).0
+    }
+
+    fn get_uv(&self, pos: Vec3) -> (f64, f64) {
+        self.get_surface(pos).1
+    }
+
+    fn get_tangent(&self, pos: Vec3) -> Vec3 {
+        self.get_surface(pos).2
+    }
+
+    fn get_surface(&self, pos: Vec3) -> (Vec3, (f64, f64), Vec3) {
+        // The closest point is searched once for all three.
+        let (patch, u, v) = self.get_closest(pos);
+        let normal = patch.get_normal(u, v);
+        let du = patch.get_point(u, v).1;
+        let tangent = if du.get_len() < EPS {
+            make_onb(normal).0
+        } else {
+            du.normalize()
+        };
+        (normal, (u, v), tangent)
+    }
+
+    fn get_bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
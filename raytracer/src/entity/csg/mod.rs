@@ -0,0 +1,148 @@
+//---------------------------------    Mod Csg    ----------------------------------------
+// Constructive solid geometry: union, intersection and difference of two closed objs,
+// combined from the intervals each one is inside along the ray. The whole solid has a
+// single material, the materials of the two objs are not used.
+use crate::entity::aabb::Aabb;
+use crate::entity::material::*;
+use crate::entity::{CanHit, Entity};
+use crate::graphics::ray::Ray;
+use crate::math_support::*;
+
+const PROBE_DIST: f64 = 0.0001; // For finding the obj whose surface a point is on.
+
+#[derive(Debug, Clone, Copy)]
+pub enum CsgOp {
+    Union,
+    Intersect,
+    Subtract, // The first without the second.
+}
+
+impl CsgOp {
+    fn is_inside(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOp::Union => in_a || in_b,
+            CsgOp::Intersect => in_a && in_b,
+            CsgOp::Subtract => in_a && !in_b,
+        }
+    }
+}
+
+//--------------------------------    Struct Csg    --------------------------------------
+
+pub struct Csg {
+    op: CsgOp,
+    a: Box<Entity>,
+    b: Box<Entity>,
+    pub material: Mat,
+}
+
+impl Csg {
+    pub fn make_csg(op: CsgOp, a: Entity, b: Entity, material: Mat) -> Csg {
+        Csg {
+            op,
+            a: Box::new(a),
+            b: Box::new(b),
+            material,
+        }
+    }
+
+    fn find_surface(&self, pos: Vec3) -> (&Entity, bool) {
+        // The obj pos is on, and whether its normal is flipped, which is the case on
+        // the second obj of a difference, whose inside is the outside of the solid.
+        let get_error = |obj: &Entity| {
+            // Crossing a surface a probe distance along the normal from below pos.
+            let normal = obj.get_hit_normal(pos);
+            let tm = obj.get_hit_time(&Ray::make_ray(pos - PROBE_DIST * normal, normal));
+            if tm < 0.0 {
+                f64::INFINITY
+            } else {
+                (tm - PROBE_DIST).abs()
+            }
+        };
+        if get_error(&self.a) <= get_error(&self.b) {
+            (&self.a, false)
+        } else {
+            (&self.b, matches!(self.op, CsgOp::Subtract))
+        }
+    }
+}
+
+impl CanHit for Csg {
+    fn get_hit_time(&self, target_ray: &Ray) -> f64 {
+        for (t0, t1) in self.get_hit_intervals(target_ray) {
+            if t0 >= EPS {
+                return t0;
+            }
+            if t1 >= EPS {
+                return t1; // From the inside.
+            }
+        }
+        -1.0
+    }
+
+    fn get_hit_intervals(&self, target_ray: &Ray) -> Vec<(f64, f64)> {
+        // Sweeps the boundaries of both in order, keeping where the op is inside.
+        let mut events: Vec<(f64, bool)> = Vec::new(); // Time, and whether it is of a.
+        for (t0, t1) in self.a.get_hit_intervals(target_ray) {
+            events.push((t0, true));
+            events.push((t1, true));
+        }
+        for (t0, t1) in self.b.get_hit_intervals(target_ray) {
+            events.push((t0, false));
+            events.push((t1, false));
+        }
+        events.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let (mut in_a, mut in_b) = (false, false);
+        let mut start = 0.0;
+        let mut res = Vec::new();
+        for (tm, of_a) in events {
+            let was_inside = self.op.is_inside(in_a, in_b);
+            if of_a {
+                in_a = !in_a;
+            } else {
+                in_b = !in_b;
+            }
+            let inside = self.op.is_inside(in_a, in_b);
+            if inside && !was_inside {
+                start = tm;
+            } else if was_inside && !inside {
+                res.push((start, tm));
+            }
+        }
+        res
+    }
+
+    fn get_hit_normal(&self, pos: Vec3) -> Vec3 {
+        self.get_surface(pos).0
+    }
+
+    fn get_uv(&self, pos: Vec3) -> (f64, f64) {
+        self.get_surface(pos).1
+    }
+
+    fn get_tangent(&self, pos: Vec3) -> Vec3 {
+        self.get_surface(pos).2
+    }
+
+    fn get_surface(&self, pos: Vec3) -> (Vec3, (f64, f64), Vec3) {
+        // The obj is found once for all three.
+        let (obj, flipped) = self.find_surface(pos);
+        let (normal, uv, tangent) = obj.get_surface(pos);
+        if flipped {
+            (crate::origin - normal, uv, tangent)
+        } else {
+            (normal, uv, tangent)
+        }
+    }
+
+    fn get_bounding_box(&self) -> Option<Aabb> {
+        match self.op {
+            CsgOp::Union => match (self.a.get_bounding_box(), self.b.get_bounding_box()) {
+                (Some(a), Some(b)) => Some(a.surround(&b)),
+                _ => None,
+            },
+            _ => self.a.get_bounding_box(), // Inside the first.
+        }
+    }
+}
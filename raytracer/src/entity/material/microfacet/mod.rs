@@ -0,0 +1,159 @@
+// Mod Microfacet: GGX (Trowbridge-Reitz) distribution with Smith masking-shadowing,
+// and Fresnel equations. Directions are in the local shading frame, whose z is the normal.
+use crate::math_support::*;
+use std::f64::consts::PI;
+
+//------------------------------    Struct Frame    ---------------------------------------
+
+pub struct Frame {
+    t: Vec3,
+    b: Vec3,
+    n: Vec3,
+}
+
+impl Frame {
+    pub fn make_frame(normal: Vec3) -> Frame {
+        let n = normal.normalize();
+        let (t, b) = make_onb(n);
+        Frame { t, b, n }
+    }
+
+    pub fn make_frame_tangent(normal: Vec3, tangent: Vec3) -> Frame {
+        // Keeps the direction of the tangent, made orthogonal to the normal.
+        let n = normal.normalize();
+        let t = tangent - dot(tangent, n) * n;
+        if t.get_len() < EPS {
+            return Frame::make_frame(n);
+        }
+        let t = t.normalize();
+        Frame {
+            t,
+            b: cross(n, t),
+            n,
+        }
+    }
+
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::make_vec3(dot(v, self.t), dot(v, self.b), dot(v, self.n))
+    }
+
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x * self.t + v.y * self.b + v.z * self.n
+    }
+}
+
+//------------------------------    Struct Ggx    -----------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl Ggx {
+    pub fn make_ggx(roughness: f64) -> Ggx {
+        Ggx::make_ggx_aniso(roughness, 0.0)
+    }
+
+    pub fn make_ggx_aniso(roughness: f64, anisotropic: f64) -> Ggx {
+        // Perceptual roughness, alpha is its square. Anisotropic from 0 to 1 stretches along x.
+        let alpha = (roughness * roughness).max(0.001);
+        let aspect = (1.0 - 0.9 * anisotropic.clamp(0.0, 1.0)).sqrt();
+        Ggx {
+            alpha_x: (alpha / aspect).max(0.001),
+            alpha_y: (alpha * aspect).max(0.001),
+        }
+    }
+
+    pub fn get_d(&self, m: Vec3) -> f64 {
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+        let e = (m.x / self.alpha_x).powi(2) + (m.y / self.alpha_y).powi(2) + m.z * m.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    fn get_lambda(&self, v: Vec3) -> f64 {
+        if v.z.abs() < EPS {
+            return f64::INFINITY;
+        }
+        let a2 = ((self.alpha_x * v.x).powi(2) + (self.alpha_y * v.y).powi(2)) / (v.z * v.z);
+        ((1.0 + a2).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn get_g1(&self, v: Vec3) -> f64 {
+        1.0 / (1.0 + self.get_lambda(v))
+    }
+
+    pub fn get_g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.get_lambda(wo) + self.get_lambda(wi))
+    }
+
+    pub fn sample_visible_normal(&self, wo: Vec3) -> Vec3 {
+        // Heitz 2018, sampling the normals visible from wo, which must be above the surface.
+        let vh = Vec3::make_vec3(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).normalize();
+        let len2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len2 > 0.0 {
+            Vec3::make_vec3(-vh.y, vh.x, 0.0) / len2.sqrt()
+        } else {
+            Vec3::make_vec3(1.0, 0.0, 0.0)
+        };
+        let t2 = cross(vh, t1);
+
+        let r = rand_0_1().sqrt();
+        let phi = 2.0 * PI * rand_0_1();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        Vec3::make_vec3(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(0.0)).normalize()
+    }
+
+    pub fn get_visible_pdf(&self, wo: Vec3, m: Vec3) -> f64 {
+        // Pdf of sample_visible_normal choosing m.
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.get_g1(wo) * dot(wo, m).max(0.0) * self.get_d(m) / wo.z
+    }
+}
+
+//------------------------------    Fresnel    --------------------------------------------
+
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    // Unpolarized reflectance, eta is the ratio of the ior behind over the ior in front.
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0; // Total internal reflection.
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    (rs * rs + rp * rp) / 2.0
+}
+
+pub fn fresnel_conductor(cos_i: f64, eta: Vec3, k: Vec3) -> Vec3 {
+    // Complex ior eta + ik, for each channel.
+    let channel = |eta: f64, k: f64| {
+        let cos_i = cos_i.clamp(0.0, 1.0);
+        let cos2 = cos_i * cos_i;
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        (rs + rp) / 2.0
+    };
+    Vec3::make_vec3(
+        channel(eta.x, k.x),
+        channel(eta.y, k.y),
+        channel(eta.z, k.z),
+    )
+}
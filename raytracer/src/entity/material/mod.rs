@@ -1,14 +1,49 @@
 // Mod Material
 #![allow(unused_variables)]
 
+pub mod microfacet;
+pub mod phase;
+pub mod principled;
+pub mod texture;
+
 use crate::graphics::ray::Ray;
+use crate::graphics::spectrum::*;
 use crate::math_support::*;
+use microfacet::*;
+pub use phase::Phase;
+pub use principled::Principled;
+use std::f64::consts::PI;
+pub use texture::*;
 
 //--------------------------    Trait Scatter    ----------------------------------------
 pub trait Scatter {
     fn do_scatter(&self, target_ray: &Ray, normal: Vec3) -> Ray;
     // Only do ray scatter, no color mixing.
     // Remember that the pos of target ray must be the hit point.
+
+    fn is_specular(&self) -> bool {
+        true // Delta distribution, which cannot be evaluated for a given direction.
+    }
+
+    fn eval(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        // BSDF times cosine of the scattered direction.
+        Vec3::make_vec3(0.0, 0.0, 0.0)
+    }
+
+    fn get_pdf(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> f64 {
+        // Solid angle pdf of do_scatter choosing out_dir.
+        0.0
+    }
+
+    fn get_weight(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        // Throughput of the direction chosen by do_scatter, which is eval / pdf.
+        let pdf = self.get_pdf(in_dir, out_dir, normal);
+        if pdf <= 0.0 {
+            Vec3::make_vec3(0.0, 0.0, 0.0)
+        } else {
+            self.eval(in_dir, out_dir, normal) / pdf
+        }
+    }
 }
 
 //---------------------------    Struct Lambertian    ------------------------------------
@@ -35,6 +70,29 @@ impl Scatter for Lambertian {
         }
         Ray::make_ray(target_ray.get_pos(), new_dir)
     }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        self.albedo * self.get_pdf(in_dir, out_dir, normal)
+    }
+
+    fn get_pdf(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> f64 {
+        // Cosine weighted, on the side the ray comes from.
+        let normal = normal.normalize();
+        let cos_theta = if dot(in_dir, normal) > -EPS {
+            -dot(out_dir.normalize(), normal)
+        } else {
+            dot(out_dir.normalize(), normal)
+        };
+        cos_theta.max(0.0) / PI
+    }
+
+    fn get_weight(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        self.albedo
+    }
 }
 
 //-------------------------------    Struct Metal    -------------------------------------
@@ -61,17 +119,33 @@ impl Scatter for Metal {
 
         Ray::make_ray(target_ray.get_pos(), new_dir)
     }
+
+    fn get_weight(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        self.albedo
+    }
 }
 
 //---------------------------    Struct Dielectric    ------------------------------------
 
 pub struct Dielectric {
     ir: f64,
+    absorption: Vec3, // Per unit length inside.
 }
 
 impl Dielectric {
     pub fn make_detc(ir: f64) -> Dielectric {
-        Dielectric { ir }
+        Dielectric {
+            ir,
+            absorption: Vec3::make_vec3(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn make_detc_tinted(ir: f64, transmittance: Vec3, dist: f64) -> Dielectric {
+        // The colour left after travelling dist inside.
+        Dielectric {
+            ir,
+            absorption: make_absorption(transmittance, dist),
+        }
     }
 
     fn reflectance(cos_theta: f64, ratio: f64) -> f64 {
@@ -91,7 +165,7 @@ impl Scatter for Dielectric {
         } else {
             self.ir
         };
-        let cos_theta = -dot(dir, normal);
+        let cos_theta = dot(dir, normal).abs().min(1.0); // Either side.
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract: bool = refraction_ratio * sin_theta > 1.0;
@@ -105,6 +179,678 @@ impl Scatter for Dielectric {
 
         Ray::make_ray(target_ray.get_pos(), new_dir)
     }
+
+    fn get_weight(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        Vec3::make_vec3(1.0, 1.0, 1.0) // Pure glass.
+    }
+}
+
+//---------------------------    Struct Conductor    -------------------------------------
+// Rough metal with GGX microfacets and Fresnel from the complex ior eta + ik.
+
+pub struct Conductor {
+    eta: Vec3,
+    k: Vec3,
+    ggx: Ggx,
+}
+
+impl Conductor {
+    pub fn make_cond(eta: Vec3, k: Vec3, roughness: f64) -> Conductor {
+        Conductor {
+            eta,
+            k,
+            ggx: Ggx::make_ggx(roughness),
+        }
+    }
+
+    // Presets, with ior at 650, 550 and 450 nm.
+    pub fn make_gold(roughness: f64) -> Conductor {
+        Conductor::make_cond(
+            Vec3::make_vec3(0.143, 0.374, 1.442),
+            Vec3::make_vec3(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn make_copper(roughness: f64) -> Conductor {
+        Conductor::make_cond(
+            Vec3::make_vec3(0.200, 0.924, 1.102),
+            Vec3::make_vec3(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn make_aluminium(roughness: f64) -> Conductor {
+        Conductor::make_cond(
+            Vec3::make_vec3(1.657, 0.880, 0.521),
+            Vec3::make_vec3(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    pub fn make_silver(roughness: f64) -> Conductor {
+        Conductor::make_cond(
+            Vec3::make_vec3(0.155, 0.117, 0.138),
+            Vec3::make_vec3(4.828, 3.122, 2.147),
+            roughness,
+        )
+    }
+
+    pub fn get_albedo(&self) -> Vec3 {
+        // Reflectance at normal incidence.
+        fresnel_conductor(1.0, self.eta, self.k)
+    }
+
+    fn to_local(in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> (Vec3, Vec3) {
+        // wo towards the viewer and wi towards the light, on the side of the viewer.
+        let normal = if dot(in_dir, normal) > 0.0 {
+            crate::origin - normal
+        } else {
+            normal
+        };
+        let frame = Frame::make_frame(normal);
+        (
+            frame.to_local(crate::origin - in_dir.normalize()),
+            frame.to_local(out_dir.normalize()),
+        )
+    }
+}
+
+impl Scatter for Conductor {
+    fn do_scatter(&self, target_ray: &Ray, normal: Vec3) -> Ray {
+        let dir = target_ray.get_dir();
+        let normal = if dot(dir, normal) > 0.0 {
+            crate::origin - normal
+        } else {
+            normal
+        };
+        let frame = Frame::make_frame(normal);
+        let wo = frame.to_local(crate::origin - dir);
+        let m = if wo.z > 0.0 {
+            frame.to_world(self.ggx.sample_visible_normal(wo))
+        } else {
+            normal
+        };
+        Ray::make_ray(target_ray.get_pos(), reflect(dir, m))
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        let (wo, wi) = Conductor::to_local(in_dir, out_dir, normal);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vec3::make_vec3(0.0, 0.0, 0.0);
+        }
+        let m = (wo + wi).normalize();
+        let f = fresnel_conductor(dot(wo, m), self.eta, self.k);
+        (self.ggx.get_d(m) * self.ggx.get_g2(wo, wi) / (4.0 * wo.z)) * f
+    }
+
+    fn get_pdf(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> f64 {
+        let (wo, wi) = Conductor::to_local(in_dir, out_dir, normal);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let m = (wo + wi).normalize();
+        // Jacobian of the reflection.
+        self.ggx.get_visible_pdf(wo, m) / (4.0 * dot(wo, m))
+    }
+
+    fn get_weight(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        let (wo, wi) = Conductor::to_local(in_dir, out_dir, normal);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vec3::make_vec3(0.0, 0.0, 0.0);
+        }
+        let m = (wo + wi).normalize();
+        let f = fresnel_conductor(dot(wo, m), self.eta, self.k);
+        (self.ggx.get_g2(wo, wi) / self.ggx.get_g1(wo)) * f
+    }
+}
+
+//------------------------    Struct RoughDielectric    ----------------------------------
+// Frosted glass, microfacet refraction of Walter et al. 2007 with GGX roughness.
+// Like Dielectric, radiance is not scaled by eta^2 when refracted.
+
+pub struct RoughDielectric {
+    ir: f64,
+    ggx: Ggx,
+    absorption: Vec3,
+}
+
+impl RoughDielectric {
+    pub fn make_rough(ir: f64, roughness: f64) -> RoughDielectric {
+        RoughDielectric {
+            ir,
+            ggx: Ggx::make_ggx(roughness),
+            absorption: Vec3::make_vec3(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn make_rough_tinted(
+        ir: f64,
+        roughness: f64,
+        transmittance: Vec3,
+        dist: f64,
+    ) -> RoughDielectric {
+        RoughDielectric {
+            ir,
+            ggx: Ggx::make_ggx(roughness),
+            absorption: make_absorption(transmittance, dist),
+        }
+    }
+
+    fn get_side(&self, in_dir: Vec3, normal: Vec3) -> (Vec3, f64) {
+        // Normal on the side of the viewer, and ior behind over ior in front.
+        let normal = normal.normalize();
+        if is_front_face(in_dir, normal) {
+            (normal, self.ir)
+        } else {
+            (crate::origin - normal, 1.0 / self.ir)
+        }
+    }
+
+    fn eval_with_pdf(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> (Vec3, f64) {
+        let none = (Vec3::make_vec3(0.0, 0.0, 0.0), 0.0);
+        let (normal, eta) = self.get_side(in_dir, normal);
+        let frame = Frame::make_frame(normal);
+        let wo = frame.to_local(crate::origin - in_dir.normalize());
+        let wi = frame.to_local(out_dir.normalize());
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return none;
+        }
+
+        // Half vector, which is the microfacet normal, of reflection or refraction.
+        let is_reflect = wi.z > 0.0;
+        let m = if is_reflect { wo + wi } else { wo + eta * wi };
+        if m.get_len() < EPS {
+            return none;
+        }
+        let m = if m.z < 0.0 {
+            crate::origin - m.normalize()
+        } else {
+            m.normalize()
+        };
+        let (cos_o, cos_i) = (dot(wo, m), dot(wi, m));
+        if cos_o <= 0.0 || (is_reflect && cos_i <= 0.0) || (!is_reflect && cos_i >= 0.0) {
+            return none; // Backfacing microfacet.
+        }
+
+        let f = fresnel_dielectric(cos_o, eta);
+        let d = self.ggx.get_d(m);
+        let g2 = self.ggx.get_g2(wo, wi);
+        let visible = self.ggx.get_g1(wo) * d * cos_o / wo.z;
+        if is_reflect {
+            let bsdf = f * d * g2 / (4.0 * wo.z);
+            (
+                Vec3::make_vec3(bsdf, bsdf, bsdf),
+                f * visible / (4.0 * cos_o),
+            )
+        } else {
+            let denom = (cos_o + eta * cos_i).powi(2);
+            let jacobian = eta * eta * -cos_i / denom;
+            let bsdf = (1.0 - f) * d * g2 * cos_o * jacobian / wo.z;
+            (
+                Vec3::make_vec3(bsdf, bsdf, bsdf),
+                (1.0 - f) * visible * jacobian,
+            )
+        }
+    }
+}
+
+impl Scatter for RoughDielectric {
+    fn do_scatter(&self, target_ray: &Ray, normal: Vec3) -> Ray {
+        let dir = target_ray.get_dir().normalize();
+        let (normal, eta) = self.get_side(dir, normal);
+        let frame = Frame::make_frame(normal);
+        let wo = frame.to_local(crate::origin - dir);
+        let m = if wo.z > 0.0 {
+            frame.to_world(self.ggx.sample_visible_normal(wo))
+        } else {
+            normal
+        };
+
+        let new_dir: Vec3 = if fresnel_dielectric(-dot(dir, m), eta) > rand_0_1() {
+            reflect(dir, m)
+        } else {
+            refract(dir, m, 1.0 / eta)
+        };
+        Ray::make_ray(target_ray.get_pos(), new_dir)
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        self.eval_with_pdf(in_dir, out_dir, normal).0
+    }
+
+    fn get_pdf(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> f64 {
+        self.eval_with_pdf(in_dir, out_dir, normal).1
+    }
+}
+
+fn make_absorption(transmittance: Vec3, dist: f64) -> Vec3 {
+    // Beer-Lambert law, transmittance = exp(-absorption * dist).
+    let channel = |t: f64| -t.clamp(1e-4, 1.0).ln() / dist.max(EPS);
+    Vec3::make_vec3(
+        channel(transmittance.x),
+        channel(transmittance.y),
+        channel(transmittance.z),
+    )
+}
+
+//---------------------------    Struct Dispersive    ------------------------------------
+// Smooth dielectric whose ior depends on the wavelength, which splits white light.
+
+pub enum Ior {
+    Cauchy(f64, f64),              // n = a + b / lambda^2, lambda in um.
+    Sellmeier([f64; 3], [f64; 3]), // n^2 = 1 + sum of b * lambda^2 / (lambda^2 - c), in um^2.
+}
+
+impl Ior {
+    pub fn get_ior(&self, lambda: f64) -> f64 {
+        // Lambda in nm, 0 for rgb rays which use the sodium d line.
+        let l = if lambda > 0.0 { lambda } else { 587.6 } / 1000.0;
+        let l2 = l * l;
+        match self {
+            Ior::Cauchy(a, b) => a + b / l2,
+            Ior::Sellmeier(b, c) => {
+                let mut n2 = 1.0;
+                for (b, c) in b.iter().zip(c.iter()) {
+                    n2 += b * l2 / (l2 - c);
+                }
+                n2.max(1.0).sqrt()
+            }
+        }
+    }
+}
+
+pub struct Dispersive {
+    ior: Ior,
+}
+
+impl Dispersive {
+    pub fn make_disp(ior: Ior) -> Dispersive {
+        Dispersive { ior }
+    }
+
+    // Presets.
+    pub fn make_bk7() -> Dispersive {
+        Dispersive::make_disp(Ior::Sellmeier(
+            [1.039_612_12, 0.231_792_344, 1.010_469_45],
+            [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+        ))
+    }
+
+    pub fn make_flint() -> Dispersive {
+        Dispersive::make_disp(Ior::Cauchy(1.728, 0.013_42))
+    }
+
+    pub fn make_diamond() -> Dispersive {
+        Dispersive::make_disp(Ior::Sellmeier(
+            [4.3356, 0.3306, 0.0],
+            [0.106 * 0.106, 0.175 * 0.175, 0.0],
+        ))
+    }
+}
+
+impl Scatter for Dispersive {
+    fn do_scatter(&self, target_ray: &Ray, normal: Vec3) -> Ray {
+        let ir = self.ior.get_ior(target_ray.get_wavelength());
+        Dielectric::make_detc(ir).do_scatter(target_ray, normal)
+    }
+
+    fn get_weight(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        Vec3::make_vec3(1.0, 1.0, 1.0)
+    }
+}
+
+//-----------------------------    Struct Coated    --------------------------------------
+// A dielectric coating over a base material, like car paint or varnish, after
+// Weidlich & Wilkie 2007: rough reflection on top, and the base seen through the layer,
+// which bends the directions, absorbs by thickness and loses what the interface reflects.
+// The base is reached by eval and get_pdf, so it should not be specular.
+
+pub struct Coated {
+    base: Box<Mat>,
+    ior: f64,
+    ggx: Ggx,
+    absorption: Vec3, // Per unit length inside the coating.
+    thickness: f64,
+}
+
+impl Coated {
+    pub fn make_coated(
+        base: Mat,
+        ior: f64,
+        roughness: f64,
+        absorption: Vec3,
+        thickness: f64,
+    ) -> Coated {
+        Coated {
+            base: Box::new(base),
+            ior,
+            ggx: Ggx::make_ggx(roughness),
+            absorption,
+            thickness,
+        }
+    }
+
+    fn bend_in(&self, w: Vec3) -> Vec3 {
+        // From a direction above the coating to the one below, both away from the surface.
+        let (x, y) = (w.x / self.ior, w.y / self.ior);
+        Vec3::make_vec3(x, y, (1.0 - x * x - y * y).max(0.0).sqrt())
+    }
+
+    fn bend_out(&self, w: Vec3) -> Option<Vec3> {
+        // None if totally reflected inside.
+        let (x, y) = (w.x * self.ior, w.y * self.ior);
+        let z2 = 1.0 - x * x - y * y;
+        if z2 <= 0.0 {
+            None
+        } else {
+            Some(Vec3::make_vec3(x, y, z2.sqrt()))
+        }
+    }
+
+    fn get_coat_prob(&self, wo: Vec3) -> f64 {
+        // Of sampling the top reflection.
+        fresnel_dielectric(wo.z, self.ior).clamp(0.1, 0.9)
+    }
+
+    fn eval_with_pdf(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> (Vec3, f64) {
+        let none = (Vec3::make_vec3(0.0, 0.0, 0.0), 0.0);
+        let normal = normal.normalize();
+        let normal = if dot(in_dir, normal) > 0.0 {
+            crate::origin - normal
+        } else {
+            normal
+        };
+        let frame = Frame::make_frame(normal);
+        let wo = frame.to_local(crate::origin - in_dir.normalize());
+        let wi = frame.to_local(out_dir.normalize());
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return none;
+        }
+        let coat_prob = self.get_coat_prob(wo);
+
+        // Reflection on top.
+        let h = (wo + wi).normalize();
+        let spec =
+            fresnel_dielectric(dot(wo, h), self.ior) * self.ggx.get_d(h) * self.ggx.get_g2(wo, wi)
+                / (4.0 * wo.z);
+        let spec_pdf = self.ggx.get_visible_pdf(wo, h) / (4.0 * dot(wo, h));
+
+        // The base, with the solid angle compressed by the refraction.
+        let (wo_in, wi_in) = (self.bend_in(wo), self.bend_in(wi));
+        let (base_in, base_out) = (crate::origin - frame.to_world(wo_in), frame.to_world(wi_in));
+        let jacobian = wi.z / (self.ior * self.ior * wi_in.z);
+        let loss =
+            (1.0 - fresnel_dielectric(wo.z, self.ior)) * (1.0 - fresnel_dielectric(wi.z, self.ior));
+        let path = self.thickness * (1.0 / wo_in.z + 1.0 / wi_in.z);
+        let transmittance = (crate::origin - path * self.absorption).exp();
+        let base = (loss * jacobian) * transmittance * self.base.eval(base_in, base_out, normal);
+        let base_pdf = self.base.get_pdf(base_in, base_out, normal) * jacobian;
+
+        (
+            Vec3::make_vec3(spec, spec, spec) + base,
+            coat_prob * spec_pdf + (1.0 - coat_prob) * base_pdf,
+        )
+    }
+}
+
+impl Scatter for Coated {
+    fn do_scatter(&self, target_ray: &Ray, normal: Vec3) -> Ray {
+        let dir = target_ray.get_dir().normalize();
+        let normal = normal.normalize();
+        let normal = if dot(dir, normal) > 0.0 {
+            crate::origin - normal
+        } else {
+            normal
+        };
+        let frame = Frame::make_frame(normal);
+        let wo = frame.to_local(crate::origin - dir);
+        if wo.z <= 0.0 {
+            return Ray::make_ray(target_ray.get_pos(), reflect(dir, normal));
+        }
+
+        if rand_0_1() < self.get_coat_prob(wo) {
+            let m = frame.to_world(self.ggx.sample_visible_normal(wo));
+            return Ray::make_ray(target_ray.get_pos(), reflect(dir, m));
+        }
+        // Into the coating, scattered by the base and out again.
+        let base_in = crate::origin - frame.to_world(self.bend_in(wo));
+        let base_out = self
+            .base
+            .scatter(&Ray::make_ray(target_ray.get_pos(), base_in), normal)
+            .get_dir();
+        let wi_in = frame.to_local(base_out);
+        let new_dir: Vec3 = match self.bend_out(wi_in) {
+            Some(wi) if wi_in.z > 0.0 => frame.to_world(wi),
+            _ => crate::origin - normal, // Absorbed, which has no weight.
+        };
+        Ray::make_ray(target_ray.get_pos(), new_dir)
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        self.eval_with_pdf(in_dir, out_dir, normal).0
+    }
+
+    fn get_pdf(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> f64 {
+        self.eval_with_pdf(in_dir, out_dir, normal).1
+    }
+}
+
+//----------------------------    Struct Subsurface    -----------------------------------
+// Smooth dielectric boundary of a scattering medium, like skin, wax, marble or milk.
+// Rays inside a closed obj take a random walk, which is done by the world.
+
+pub struct Subsurface {
+    ir: f64,
+    sigma_t: Vec3, // Extinction, the inverse of the mean free path.
+    albedo: Vec3,  // Single scattering albedo.
+    phase: Phase,
+}
+
+impl Subsurface {
+    pub fn make_sss(ir: f64, mean_free_path: Vec3, albedo: Vec3, phase: Phase) -> Subsurface {
+        let channel = |mfp: f64| 1.0 / mfp.max(EPS);
+        Subsurface {
+            ir,
+            sigma_t: Vec3::make_vec3(
+                channel(mean_free_path.x),
+                channel(mean_free_path.y),
+                channel(mean_free_path.z),
+            ),
+            albedo,
+            phase,
+        }
+    }
+}
+
+impl Scatter for Subsurface {
+    fn do_scatter(&self, target_ray: &Ray, normal: Vec3) -> Ray {
+        Dielectric::make_detc(self.ir).do_scatter(target_ray, normal)
+    }
+
+    fn get_weight(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        Vec3::make_vec3(1.0, 1.0, 1.0)
+    }
+}
+
+//------------------------------    Struct Medium    -------------------------------------
+// What a collision inside a volume does: keeps the albedo and turns the ray by the phase.
+
+pub struct Medium {
+    albedo: Vec3, // Single scattering albedo.
+    phase: Phase,
+}
+
+impl Medium {
+    pub fn make_medium(albedo: Vec3, phase: Phase) -> Medium {
+        Medium { albedo, phase }
+    }
+}
+
+impl Scatter for Medium {
+    fn do_scatter(&self, target_ray: &Ray, normal: Vec3) -> Ray {
+        Ray::make_ray(
+            target_ray.get_pos(),
+            self.phase.sample(target_ray.get_dir()),
+        )
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        self.phase.eval(in_dir, out_dir) * self.albedo
+    }
+
+    fn get_pdf(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> f64 {
+        self.phase.eval(in_dir, out_dir)
+    }
+
+    fn get_weight(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        self.albedo
+    }
+}
+
+//------------------------------    Struct Mapped    -------------------------------------
+// Any material, shaded with the normal perturbed by a normal or bump map.
+// The world perturbs the normal first, so the base only sees the shading normal.
+
+pub struct Mapped {
+    pub base: Box<Mat>,
+    map: NormalMap,
+}
+
+impl Mapped {
+    pub fn make_mapped(base: Mat, map: NormalMap) -> Mapped {
+        Mapped {
+            base: Box::new(base),
+            map,
+        }
+    }
+}
+
+impl Scatter for Mapped {
+    fn do_scatter(&self, target_ray: &Ray, normal: Vec3) -> Ray {
+        self.base.scatter(target_ray, normal)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.base.is_specular()
+    }
+
+    fn eval(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        self.base.eval(in_dir, out_dir, normal)
+    }
+
+    fn get_pdf(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> f64 {
+        self.base.get_pdf(in_dir, out_dir, normal)
+    }
+
+    fn get_weight(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        self.base.get_weight(in_dir, out_dir, normal)
+    }
+}
+
+//------------------------------    Struct Cutout    -------------------------------------
+// Any material with an opacity mask. The world does not hit it where the mask is
+// transparent, and hits it with the probability of the alpha where it is fractional.
+
+pub struct Cutout {
+    pub base: Box<Mat>,
+    opacity: Opacity,
+}
+
+impl Cutout {
+    pub fn make_cutout(base: Mat, opacity: Opacity) -> Cutout {
+        Cutout {
+            base: Box::new(base),
+            opacity,
+        }
+    }
+}
+
+impl Scatter for Cutout {
+    fn do_scatter(&self, target_ray: &Ray, normal: Vec3) -> Ray {
+        self.base.scatter(target_ray, normal)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.base.is_specular()
+    }
+
+    fn eval(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        self.base.eval(in_dir, out_dir, normal)
+    }
+
+    fn get_pdf(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> f64 {
+        self.base.get_pdf(in_dir, out_dir, normal)
+    }
+
+    fn get_weight(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        self.base.get_weight(in_dir, out_dir, normal)
+    }
+}
+
+//---------------------------    Struct DiffuseLight    ----------------------------------
+
+pub struct DiffuseLight {
+    pub emit: Vec3,
+    kelvin: f64, // Blackbody temperature, 0 if the emission is rgb.
+    scale: f64,  // From Planck's law to the emission.
+}
+
+impl DiffuseLight {
+    pub fn make_light(emit: Vec3) -> DiffuseLight {
+        DiffuseLight {
+            emit,
+            kelvin: 0.0,
+            scale: 0.0,
+        }
+    }
+
+    pub fn make_blackbody(kelvin: f64, intensity: f64) -> DiffuseLight {
+        // Intensity is the luminance of the emission.
+        DiffuseLight {
+            emit: intensity * get_blackbody_rgb(kelvin),
+            kelvin,
+            scale: intensity / get_blackbody_norm(kelvin),
+        }
+    }
+
+    pub fn get_emission(&self, in_dir: Vec3, normal: Vec3, lambdas: Vec3) -> Vec3 {
+        // Emits on the front side only. Spectral at lambdas, or rgb if they are 0.
+        if dot(in_dir, normal) >= 0.0 {
+            Vec3::make_vec3(0.0, 0.0, 0.0)
+        } else if self.kelvin > 0.0 && lambdas.x > 0.0 {
+            self.scale
+                * Vec3::make_vec3(
+                    get_blackbody(lambdas.x, self.kelvin),
+                    get_blackbody(lambdas.y, self.kelvin),
+                    get_blackbody(lambdas.z, self.kelvin),
+                )
+        } else {
+            illuminant_to_spectrum(self.emit, lambdas)
+        }
+    }
+}
+
+impl Scatter for DiffuseLight {
+    fn do_scatter(&self, target_ray: &Ray, normal: Vec3) -> Ray {
+        *target_ray // Never scatters, the path ends here.
+    }
 }
 
 //---------------------------    Enum for Materials    -----------------------------------
@@ -112,6 +858,16 @@ pub enum Mat {
     Lmb(Lambertian),
     Mtl(Metal),
     Detc(Dielectric),
+    Emit(DiffuseLight),
+    Cond(Conductor),
+    Rough(RoughDielectric),
+    Disp(Dispersive),
+    Prin(Principled),
+    Coat(Coated),
+    Sss(Subsurface),
+    Med(Medium),
+    Map(Mapped),
+    Cut(Cutout),
 }
 
 impl Mat {
@@ -129,11 +885,268 @@ impl Mat {
         Mat::Detc(Dielectric::make_detc(ir))
     }
 
+    pub fn make_mat_detc_tinted(ir: f64, transmittance: Vec3, dist: f64) -> Mat {
+        // Absorbing glass, with the colour left after travelling dist inside.
+        Mat::Detc(Dielectric::make_detc_tinted(ir, transmittance, dist))
+    }
+
+    pub fn make_mat_cond(eta: Vec3, k: Vec3, roughness: f64) -> Mat {
+        // Complex index of refraction eta + ik with GGX roughness.
+        Mat::Cond(Conductor::make_cond(eta, k, roughness))
+    }
+
+    pub fn make_mat_rough(ir: f64, roughness: f64) -> Mat {
+        // Index of refraction with GGX roughness.
+        Mat::Rough(RoughDielectric::make_rough(ir, roughness))
+    }
+
+    pub fn make_mat_rough_tinted(ir: f64, roughness: f64, transmittance: Vec3, dist: f64) -> Mat {
+        Mat::Rough(RoughDielectric::make_rough_tinted(
+            ir,
+            roughness,
+            transmittance,
+            dist,
+        ))
+    }
+
+    pub fn make_mat_disp(ior: Ior) -> Mat {
+        // Ior from the wavelength.
+        Mat::Disp(Dispersive::make_disp(ior))
+    }
+
+    pub fn make_mat_principled(base_color: Vec3) -> Mat {
+        // Default parameters, see Principled.
+        Mat::Prin(Principled::make_principled(base_color))
+    }
+
+    pub fn make_mat_coated(
+        base: Mat,
+        ior: f64,
+        roughness: f64,
+        absorption: Vec3,
+        thickness: f64,
+    ) -> Mat {
+        // Coating of ior and GGX roughness, absorbing per unit length, over the base.
+        Mat::Coat(Coated::make_coated(
+            base, ior, roughness, absorption, thickness,
+        ))
+    }
+
+    pub fn make_mat_sss(ir: f64, mean_free_path: Vec3, albedo: Vec3, phase: Phase) -> Mat {
+        // Mean free path and single scattering albedo per channel, and the phase function.
+        Mat::Sss(Subsurface::make_sss(ir, mean_free_path, albedo, phase))
+    }
+
+    pub fn make_mat_medium(albedo: Vec3, phase: Phase) -> Mat {
+        // Single scattering albedo and phase function.
+        Mat::Med(Medium::make_medium(albedo, phase))
+    }
+
+    pub fn make_mat_mapped(base: Mat, map: NormalMap) -> Mat {
+        // The base shaded with the normal from the map.
+        Mat::Map(Mapped::make_mapped(base, map))
+    }
+
+    pub fn make_mat_cutout(base: Mat, opacity: Opacity) -> Mat {
+        // The base, cut out where the opacity is 0.
+        Mat::Cut(Cutout::make_cutout(base, opacity))
+    }
+
+    pub fn make_mat_emit(x: f64, y: f64, z: f64) -> Mat {
+        // Emitted radiance.
+        Mat::Emit(DiffuseLight::make_light(Vec3::make_vec3(x, y, z)))
+    }
+
+    pub fn make_mat_blackbody(kelvin: f64, intensity: f64) -> Mat {
+        // Temperature in Kelvin, with the luminance of the emission.
+        Mat::Emit(DiffuseLight::make_blackbody(kelvin, intensity))
+    }
+
+    pub fn does_scatter(&self) -> bool {
+        match self {
+            Mat::Emit(_) => false,
+            Mat::Map(tmp) => tmp.base.does_scatter(),
+            Mat::Cut(tmp) => tmp.base.does_scatter(),
+            _ => true,
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        // Only scatters rays of a single wavelength.
+        match self {
+            Mat::Disp(_) => true,
+            Mat::Map(tmp) => tmp.base.is_dispersive(),
+            Mat::Cut(tmp) => tmp.base.is_dispersive(),
+            _ => false,
+        }
+    }
+
+    pub fn get_emit(&self) -> Vec3 {
+        // Emitted radiance on the front side.
+        match self {
+            Mat::Emit(tmp) => tmp.emit,
+            Mat::Map(tmp) => tmp.base.get_emit(),
+            Mat::Cut(tmp) => tmp.base.get_emit(),
+            _ => Vec3::make_vec3(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn get_emission(&self, in_dir: Vec3, normal: Vec3, lambdas: Vec3) -> Vec3 {
+        match self {
+            Mat::Emit(tmp) => tmp.get_emission(in_dir, normal, lambdas),
+            Mat::Map(tmp) => tmp.base.get_emission(in_dir, normal, lambdas),
+            Mat::Cut(tmp) => tmp.base.get_emission(in_dir, normal, lambdas),
+            _ => Vec3::make_vec3(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn get_absorption(&self) -> Vec3 {
+        // Absorption coefficient of the medium behind the surface.
+        match self {
+            Mat::Detc(tmp) => tmp.absorption,
+            Mat::Rough(tmp) => tmp.absorption,
+            Mat::Map(tmp) => tmp.base.get_absorption(),
+            Mat::Cut(tmp) => tmp.base.get_absorption(),
+            _ => Vec3::make_vec3(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn get_medium(&self) -> Option<(Vec3, Vec3, Phase)> {
+        // Extinction, single scattering albedo and phase of the medium behind the surface.
+        match self {
+            Mat::Sss(tmp) => Some((tmp.sigma_t, tmp.albedo, tmp.phase)),
+            Mat::Map(tmp) => tmp.base.get_medium(),
+            Mat::Cut(tmp) => tmp.base.get_medium(),
+            _ => None,
+        }
+    }
+
+    pub fn get_shading_normal(
+        &self,
+        in_dir: Vec3,
+        normal: Vec3,
+        tangent: Vec3,
+        uv: (f64, f64),
+    ) -> Vec3 {
+        // The geometric normal, unless a map perturbs it. A perturbed normal
+        // facing the other side of the ray would leak light, so it is not used.
+        match self {
+            Mat::Map(tmp) => {
+                let shading = tmp.map.get_normal(normal, tangent, uv);
+                if is_front_face(in_dir, shading) == is_front_face(in_dir, normal) {
+                    shading
+                } else {
+                    normal
+                }
+            }
+            Mat::Cut(tmp) => tmp.base.get_shading_normal(in_dir, normal, tangent, uv),
+            _ => normal,
+        }
+    }
+
+    pub fn is_cutout(&self) -> bool {
+        match self {
+            Mat::Cut(_) => true,
+            Mat::Map(tmp) => tmp.base.is_cutout(),
+            _ => false,
+        }
+    }
+
+    pub fn get_opacity(&self, uv: (f64, f64)) -> f64 {
+        match self {
+            Mat::Cut(tmp) => tmp.opacity.get_alpha(uv),
+            Mat::Map(tmp) => tmp.base.get_opacity(uv),
+            _ => 1.0,
+        }
+    }
+
     pub fn scatter(&self, target_ray: &Ray, normal: Vec3) -> Ray {
         match self {
             Mat::Lmb(tmp) => tmp.do_scatter(target_ray, normal),
             Mat::Mtl(tmp) => tmp.do_scatter(target_ray, normal),
             Mat::Detc(tmp) => tmp.do_scatter(target_ray, normal),
+            Mat::Emit(tmp) => tmp.do_scatter(target_ray, normal),
+            Mat::Cond(tmp) => tmp.do_scatter(target_ray, normal),
+            Mat::Rough(tmp) => tmp.do_scatter(target_ray, normal),
+            Mat::Disp(tmp) => tmp.do_scatter(target_ray, normal),
+            Mat::Prin(tmp) => tmp.do_scatter(target_ray, normal),
+            Mat::Coat(tmp) => tmp.do_scatter(target_ray, normal),
+            Mat::Sss(tmp) => tmp.do_scatter(target_ray, normal),
+            Mat::Med(tmp) => tmp.do_scatter(target_ray, normal),
+            Mat::Map(tmp) => tmp.do_scatter(target_ray, normal),
+            Mat::Cut(tmp) => tmp.do_scatter(target_ray, normal),
+        }
+    }
+
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Mat::Lmb(tmp) => tmp.is_specular(),
+            Mat::Mtl(tmp) => tmp.is_specular(),
+            Mat::Detc(tmp) => tmp.is_specular(),
+            Mat::Emit(tmp) => tmp.is_specular(),
+            Mat::Cond(tmp) => tmp.is_specular(),
+            Mat::Rough(tmp) => tmp.is_specular(),
+            Mat::Disp(tmp) => tmp.is_specular(),
+            Mat::Prin(tmp) => tmp.is_specular(),
+            Mat::Coat(tmp) => tmp.is_specular(),
+            Mat::Sss(tmp) => tmp.is_specular(),
+            Mat::Med(tmp) => tmp.is_specular(),
+            Mat::Map(tmp) => tmp.is_specular(),
+            Mat::Cut(tmp) => tmp.is_specular(),
+        }
+    }
+
+    pub fn eval(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        match self {
+            Mat::Lmb(tmp) => tmp.eval(in_dir, out_dir, normal),
+            Mat::Mtl(tmp) => tmp.eval(in_dir, out_dir, normal),
+            Mat::Detc(tmp) => tmp.eval(in_dir, out_dir, normal),
+            Mat::Emit(tmp) => tmp.eval(in_dir, out_dir, normal),
+            Mat::Cond(tmp) => tmp.eval(in_dir, out_dir, normal),
+            Mat::Rough(tmp) => tmp.eval(in_dir, out_dir, normal),
+            Mat::Disp(tmp) => tmp.eval(in_dir, out_dir, normal),
+            Mat::Prin(tmp) => tmp.eval(in_dir, out_dir, normal),
+            Mat::Coat(tmp) => tmp.eval(in_dir, out_dir, normal),
+            Mat::Sss(tmp) => tmp.eval(in_dir, out_dir, normal),
+            Mat::Med(tmp) => tmp.eval(in_dir, out_dir, normal),
+            Mat::Map(tmp) => tmp.eval(in_dir, out_dir, normal),
+            Mat::Cut(tmp) => tmp.eval(in_dir, out_dir, normal),
+        }
+    }
+
+    pub fn get_pdf(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> f64 {
+        match self {
+            Mat::Lmb(tmp) => tmp.get_pdf(in_dir, out_dir, normal),
+            Mat::Mtl(tmp) => tmp.get_pdf(in_dir, out_dir, normal),
+            Mat::Detc(tmp) => tmp.get_pdf(in_dir, out_dir, normal),
+            Mat::Emit(tmp) => tmp.get_pdf(in_dir, out_dir, normal),
+            Mat::Cond(tmp) => tmp.get_pdf(in_dir, out_dir, normal),
+            Mat::Rough(tmp) => tmp.get_pdf(in_dir, out_dir, normal),
+            Mat::Disp(tmp) => tmp.get_pdf(in_dir, out_dir, normal),
+            Mat::Prin(tmp) => tmp.get_pdf(in_dir, out_dir, normal),
+            Mat::Coat(tmp) => tmp.get_pdf(in_dir, out_dir, normal),
+            Mat::Sss(tmp) => tmp.get_pdf(in_dir, out_dir, normal),
+            Mat::Med(tmp) => tmp.get_pdf(in_dir, out_dir, normal),
+            Mat::Map(tmp) => tmp.get_pdf(in_dir, out_dir, normal),
+            Mat::Cut(tmp) => tmp.get_pdf(in_dir, out_dir, normal),
+        }
+    }
+
+    pub fn get_weight(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        match self {
+            Mat::Lmb(tmp) => tmp.get_weight(in_dir, out_dir, normal),
+            Mat::Mtl(tmp) => tmp.get_weight(in_dir, out_dir, normal),
+            Mat::Detc(tmp) => tmp.get_weight(in_dir, out_dir, normal),
+            Mat::Emit(tmp) => tmp.get_weight(in_dir, out_dir, normal),
+            Mat::Cond(tmp) => tmp.get_weight(in_dir, out_dir, normal),
+            Mat::Rough(tmp) => tmp.get_weight(in_dir, out_dir, normal),
+            Mat::Disp(tmp) => tmp.get_weight(in_dir, out_dir, normal),
+            Mat::Prin(tmp) => tmp.get_weight(in_dir, out_dir, normal),
+            Mat::Coat(tmp) => tmp.get_weight(in_dir, out_dir, normal),
+            Mat::Sss(tmp) => tmp.get_weight(in_dir, out_dir, normal),
+            Mat::Med(tmp) => tmp.get_weight(in_dir, out_dir, normal),
+            Mat::Map(tmp) => tmp.get_weight(in_dir, out_dir, normal),
+            Mat::Cut(tmp) => tmp.get_weight(in_dir, out_dir, normal),
         }
     }
 
@@ -141,7 +1154,17 @@ impl Mat {
         match self {
             Mat::Lmb(tmp) => tmp.albedo,
             Mat::Mtl(tmp) => tmp.albedo,
-            Mat::Detc(tmp) => Vec3::make_vec3(1.0, 1.0, 1.0), // Pure glass.
+            Mat::Detc(tmp) => (crate::origin - tmp.absorption).exp(), // Through unit length.
+            Mat::Emit(tmp) => Vec3::make_vec3(0.0, 0.0, 0.0),
+            Mat::Cond(tmp) => tmp.get_albedo(),
+            Mat::Rough(tmp) => (crate::origin - tmp.absorption).exp(),
+            Mat::Disp(tmp) => Vec3::make_vec3(1.0, 1.0, 1.0),
+            Mat::Prin(tmp) => tmp.base_color,
+            Mat::Coat(tmp) => tmp.base.get_albedo(),
+            Mat::Sss(tmp) => tmp.albedo,
+            Mat::Med(tmp) => tmp.albedo,
+            Mat::Map(tmp) => tmp.base.get_albedo(),
+            Mat::Cut(tmp) => tmp.base.get_albedo(),
         }
     }
 }
@@ -0,0 +1,56 @@
+// Mod Phase: phase functions, the angular distribution of light scattered inside media.
+// Directions are those of travel, so cos_theta = 1 goes straight on.
+use crate::math_support::*;
+use std::f64::consts::PI;
+
+//------------------------------    Enum Phase    -----------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub enum Phase {
+    Iso,
+    Hg(f64), // Henyey-Greenstein of asymmetry g, which scatters forward if g > 0.
+    Rayleigh,
+}
+
+impl Phase {
+    pub fn make_hg(g: f64) -> Phase {
+        Phase::Hg(g.clamp(-0.99, 0.99))
+    }
+
+    pub fn eval(&self, in_dir: Vec3, out_dir: Vec3) -> f64 {
+        // Per steradian, which is also the pdf of sample.
+        let cos_theta = dot(in_dir.normalize(), out_dir.normalize());
+        match self {
+            Phase::Iso => 1.0 / (4.0 * PI),
+            Phase::Hg(g) => {
+                let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+                (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+            }
+            Phase::Rayleigh => 3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta),
+        }
+    }
+
+    pub fn sample(&self, in_dir: Vec3) -> Vec3 {
+        let u = rand_0_1();
+        let cos_theta = match self {
+            Phase::Iso => 1.0 - 2.0 * u,
+            Phase::Hg(g) if g.abs() < 1e-3 => 1.0 - 2.0 * u,
+            Phase::Hg(g) => {
+                let t = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+                (1.0 + g * g - t * t) / (2.0 * g)
+            }
+            Phase::Rayleigh => {
+                // Root of cos^3 + 3 cos = 4 (2u - 1), by Cardano.
+                let z = 2.0 * (2.0 * u - 1.0);
+                let a = (z + (z * z + 1.0).sqrt()).cbrt();
+                a - 1.0 / a
+            }
+        }
+        .clamp(-1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rand_0_1();
+        let dir = in_dir.normalize();
+        let (t, b) = make_onb(dir);
+        (sin_theta * phi.cos()) * t + (sin_theta * phi.sin()) * b + cos_theta * dir
+    }
+}
@@ -0,0 +1,223 @@
+//---------------------------    Struct Principled    ------------------------------------
+// Disney principled BSDF (Burley 2012, 2015): diffuse with retro-reflection and sheen,
+// GGX specular, GTR1 clearcoat and rough transmission, mixed by a few artist parameters.
+use super::microfacet::*;
+use super::{RoughDielectric, Scatter};
+use crate::graphics::ray::Ray;
+use crate::math_support::*;
+use std::f64::consts::PI;
+
+pub struct Principled {
+    pub base_color: Vec3,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub specular: f64, // 0.5 is the reflectance of ior 1.5.
+    pub specular_tint: f64,
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    pub clearcoat: f64,
+    pub clearcoat_gloss: f64,
+    pub transmission: f64,
+    pub anisotropic: f64,
+    pub ior: f64, // Of the transmission.
+}
+
+impl Principled {
+    pub fn make_principled(base_color: Vec3) -> Principled {
+        // Rough plastic, other looks by struct update syntax.
+        Principled {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            anisotropic: 0.0,
+            ior: 1.5,
+        }
+    }
+
+    fn get_tint(&self) -> Vec3 {
+        // Hue and saturation of the base colour.
+        let lum = luminance(self.base_color);
+        if lum > 0.0 {
+            self.base_color / lum
+        } else {
+            Vec3::make_vec3(1.0, 1.0, 1.0)
+        }
+    }
+
+    fn get_spec0(&self) -> Vec3 {
+        // Specular reflectance at normal incidence.
+        let white = Vec3::make_vec3(1.0, 1.0, 1.0);
+        let dielectric = (0.08 * self.specular) * lerp(white, self.get_tint(), self.specular_tint);
+        lerp(dielectric, self.base_color, self.metallic)
+    }
+
+    fn get_weights(&self) -> (f64, f64) {
+        // Of the diffuse and of the transmission.
+        let dielectric = 1.0 - self.metallic.clamp(0.0, 1.0);
+        let transmission = self.transmission.clamp(0.0, 1.0);
+        (dielectric * (1.0 - transmission), dielectric * transmission)
+    }
+
+    fn get_lobe_probs(&self) -> [f64; 4] {
+        // Of sampling the diffuse, specular, clearcoat and transmission lobes.
+        let (diffuse, transmission) = self.get_weights();
+        let specular = (1.0 - transmission) * luminance(self.get_spec0()).max(0.1);
+        let clearcoat = 0.25 * self.clearcoat.max(0.0);
+        let sum = diffuse + specular + clearcoat + transmission;
+        [
+            diffuse / sum,
+            specular / sum,
+            clearcoat / sum,
+            transmission / sum,
+        ]
+    }
+
+    fn get_ggx(&self) -> Ggx {
+        Ggx::make_ggx_aniso(self.roughness, self.anisotropic)
+    }
+
+    fn get_glass(&self) -> RoughDielectric {
+        RoughDielectric::make_rough(self.ior, self.roughness)
+    }
+
+    fn get_clearcoat_alpha(&self) -> f64 {
+        0.1 * (1.0 - self.clearcoat_gloss) + 0.001 * self.clearcoat_gloss
+    }
+
+    fn eval_with_pdf(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> (Vec3, f64) {
+        let probs = self.get_lobe_probs();
+        let (diffuse_weight, trans_weight) = self.get_weights();
+        let mut f = Vec3::make_vec3(0.0, 0.0, 0.0);
+        let mut pdf = 0.0;
+
+        if probs[3] > 0.0 {
+            let glass = self.get_glass();
+            let tint = self.base_color.max_with(0.0).sqrt_for_gamma_correction();
+            f = f + trans_weight * tint * glass.eval(in_dir, out_dir, normal);
+            pdf += probs[3] * glass.get_pdf(in_dir, out_dir, normal);
+        }
+
+        let frame = Frame::make_frame(facing_normal(in_dir, normal));
+        let wo = frame.to_local(crate::origin - in_dir.normalize());
+        let wi = frame.to_local(out_dir.normalize());
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return (f, pdf);
+        }
+        let h = (wo + wi).normalize();
+        let (cos_d, cos_oh) = (dot(wi, h), dot(wo, h));
+        let white = Vec3::make_vec3(1.0, 1.0, 1.0);
+
+        // Diffuse with retro-reflection at grazing angles, and sheen.
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let (fl, fv) = (schlick_weight(wi.z), schlick_weight(wo.z));
+        let diffuse =
+            ((1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv) / PI) * self.base_color;
+        let sheen =
+            (self.sheen * schlick_weight(cos_d)) * lerp(white, self.get_tint(), self.sheen_tint);
+        f = f + (diffuse_weight * wi.z) * (diffuse + sheen);
+        pdf += probs[0] * wi.z / PI;
+
+        // Specular, whose reflection of the transmissive part is in the glass.
+        let ggx = self.get_ggx();
+        let spec0 = self.get_spec0();
+        let fr = spec0 + schlick_weight(cos_oh) * (white - spec0);
+        let spec = (1.0 - trans_weight) * ggx.get_d(h) * ggx.get_g2(wo, wi) / (4.0 * wo.z);
+        f = f + spec * fr;
+        pdf += probs[1] * ggx.get_visible_pdf(wo, h) / (4.0 * cos_oh);
+
+        // Clearcoat, a fixed ior 1.5 layer with GTR1 distribution.
+        if probs[2] > 0.0 {
+            let d = get_gtr1(h.z, self.get_clearcoat_alpha());
+            let g = Ggx::make_ggx(0.5).get_g2(wo, wi);
+            let fc = 0.04 + 0.96 * schlick_weight(cos_oh);
+            let coat = 0.25 * self.clearcoat * d * g * fc / (4.0 * wo.z);
+            f = f + Vec3::make_vec3(coat, coat, coat);
+            pdf += probs[2] * d * h.z / (4.0 * cos_oh);
+        }
+        (f, pdf)
+    }
+}
+
+impl Scatter for Principled {
+    fn do_scatter(&self, target_ray: &Ray, normal: Vec3) -> Ray {
+        let probs = self.get_lobe_probs();
+        let dir = target_ray.get_dir().normalize();
+        let u = rand_0_1();
+        if u >= probs[0] + probs[1] + probs[2] {
+            return self.get_glass().do_scatter(target_ray, normal);
+        }
+
+        let normal = facing_normal(dir, normal);
+        let frame = Frame::make_frame(normal);
+        let wo = frame.to_local(crate::origin - dir);
+        let new_dir: Vec3 = if u < probs[0] || wo.z <= 0.0 {
+            // Cosine weighted.
+            let new_dir = normal + rand_normalized_vec();
+            if close_to(new_dir.get_len(), 0.0) {
+                normal
+            } else {
+                new_dir
+            }
+        } else if u < probs[0] + probs[1] {
+            reflect(
+                dir,
+                frame.to_world(self.get_ggx().sample_visible_normal(wo)),
+            )
+        } else {
+            // Half vector from the GTR1 distribution.
+            let a2 = self.get_clearcoat_alpha().powi(2);
+            let cos_h = ((1.0 - a2.powf(1.0 - rand_0_1())) / (1.0 - a2))
+                .clamp(0.0, 1.0)
+                .sqrt();
+            let sin_h = (1.0 - cos_h * cos_h).sqrt();
+            let phi = 2.0 * PI * rand_0_1();
+            let h = Vec3::make_vec3(sin_h * phi.cos(), sin_h * phi.sin(), cos_h);
+            reflect(dir, frame.to_world(h))
+        };
+        Ray::make_ray(target_ray.get_pos(), new_dir)
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> Vec3 {
+        self.eval_with_pdf(in_dir, out_dir, normal).0
+    }
+
+    fn get_pdf(&self, in_dir: Vec3, out_dir: Vec3, normal: Vec3) -> f64 {
+        self.eval_with_pdf(in_dir, out_dir, normal).1
+    }
+}
+
+fn facing_normal(in_dir: Vec3, normal: Vec3) -> Vec3 {
+    // The normal on the side the ray comes from.
+    let normal = normal.normalize();
+    if dot(in_dir, normal) > 0.0 {
+        crate::origin - normal
+    } else {
+        normal
+    }
+}
+
+fn lerp(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+    (1.0 - t) * a + t * b
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+fn get_gtr1(cos_h: f64, alpha: f64) -> f64 {
+    // Generalized Trowbridge-Reitz with gamma 1, which has a longer tail.
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * cos_h * cos_h;
+    (a2 - 1.0) / (PI * a2.ln() * t)
+}
@@ -0,0 +1,161 @@
+// Mod Texture: images, heights and opacities looked up by the uv of the surface, and the
+// maps that perturb the shading normal with them in the tangent frame of the surface.
+use super::microfacet::Frame;
+use crate::math_support::*;
+
+const BUMP_DELTA: f64 = 0.001; // In uv, for the slope of the height.
+
+//--------------------------    Struct ImageTexture    -----------------------------------
+
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>, // From the top row, in [0, 1]. Maps are data, so not gamma decoded.
+}
+
+impl ImageTexture {
+    pub fn load(path: &str) -> Result<ImageTexture, String> {
+        let img = image::open(path).map_err(|err| err.to_string())?.to_rgb8();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        if width == 0 || height == 0 {
+            return Err(format!("{} is empty", path));
+        }
+        let pixels = img
+            .pixels()
+            .map(|p| Vec3::make_vec3(p[0] as f64, p[1] as f64, p[2] as f64) / 255.0)
+            .collect();
+        Ok(ImageTexture {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn load_alpha(path: &str) -> Result<ImageTexture, String> {
+        // Gray, of the alpha channel, or of the luminance if the image has none.
+        let img = image::open(path).map_err(|err| err.to_string())?;
+        let has_alpha = img.color().has_alpha();
+        let img = img.to_rgba8();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        if width == 0 || height == 0 {
+            return Err(format!("{} is empty", path));
+        }
+        let pixels = img
+            .pixels()
+            .map(|p| {
+                let a = if has_alpha {
+                    p[3] as f64 / 255.0
+                } else {
+                    luminance(Vec3::make_vec3(p[0] as f64, p[1] as f64, p[2] as f64) / 255.0)
+                };
+                Vec3::make_vec3(a, a, a)
+            })
+            .collect();
+        Ok(ImageTexture {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    fn get_pixel(&self, i: i64, j: i64) -> Vec3 {
+        // Tiled.
+        let i = i.rem_euclid(self.width as i64) as usize;
+        let j = j.rem_euclid(self.height as i64) as usize;
+        self.pixels[j * self.width + i]
+    }
+
+    pub fn get_value(&self, u: f64, v: f64) -> Vec3 {
+        // Bilinear, v = 0 at the bottom row.
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (i, j) = (x.floor(), y.floor());
+        let (fx, fy) = (x - i, y - j);
+        let (i, j) = (i as i64, j as i64);
+        (1.0 - fy) * ((1.0 - fx) * self.get_pixel(i, j) + fx * self.get_pixel(i + 1, j))
+            + fy * ((1.0 - fx) * self.get_pixel(i, j + 1) + fx * self.get_pixel(i + 1, j + 1))
+    }
+}
+
+//------------------------------    Enum Height    ----------------------------------------
+
+pub enum Height {
+    Image(ImageTexture), // Luminance of the image.
+    Noise(f64),          // Fractal value noise of the frequency, in [0, 1).
+}
+
+impl Height {
+    pub fn get_height(&self, u: f64, v: f64) -> f64 {
+        match self {
+            Height::Image(tmp) => luminance(tmp.get_value(u, v)),
+            Height::Noise(freq) => get_fbm(Vec3::make_vec3(freq * u, freq * v, 0.0)),
+        }
+    }
+}
+
+//------------------------------    Enum Opacity    ---------------------------------------
+
+pub enum Opacity {
+    Image(ImageTexture), // Loaded by load_alpha.
+    Noise(f64),          // Blotches of fractal value noise of the frequency, with soft edges.
+}
+
+impl Opacity {
+    pub fn make_opacity(path: &str) -> Result<Opacity, String> {
+        Ok(Opacity::Image(ImageTexture::load_alpha(path)?))
+    }
+
+    pub fn get_alpha(&self, uv: (f64, f64)) -> f64 {
+        // 0 where the surface is cut out, 1 where it is solid.
+        let (u, v) = uv;
+        match self {
+            Opacity::Image(tmp) => tmp.get_value(u, v).x,
+            Opacity::Noise(freq) => {
+                let noise = get_fbm(Vec3::make_vec3(freq * u, freq * v, 0.0));
+                (10.0 * (noise - 0.5) + 0.5).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+//-----------------------------    Enum NormalMap    -------------------------------------
+
+pub enum NormalMap {
+    Tangent(ImageTexture, f64), // Tangent space normals, with the strength of the bumps.
+    Bump(Height, f64),          // Height field, with the scale of the heights.
+}
+
+impl NormalMap {
+    pub fn make_tangent(path: &str, strength: f64) -> Result<NormalMap, String> {
+        Ok(NormalMap::Tangent(ImageTexture::load(path)?, strength))
+    }
+
+    pub fn make_bump(height: Height, scale: f64) -> NormalMap {
+        NormalMap::Bump(height, scale)
+    }
+
+    pub fn get_normal(&self, normal: Vec3, tangent: Vec3, uv: (f64, f64)) -> Vec3 {
+        // The shading normal at uv. The tangent points to increasing u.
+        let frame = Frame::make_frame_tangent(normal, tangent);
+        let (u, v) = uv;
+        let local = match self {
+            NormalMap::Tangent(image, strength) => {
+                let c = 2.0 * image.get_value(u, v) - Vec3::make_vec3(1.0, 1.0, 1.0);
+                Vec3::make_vec3(strength * c.x, strength * c.y, c.z.max(EPS))
+            }
+            NormalMap::Bump(height, scale) => {
+                // Leans against the slope, by central differences.
+                let slope = |du: f64, dv: f64| {
+                    (height.get_height(u + du, v + dv) - height.get_height(u - du, v - dv))
+                        / (2.0 * BUMP_DELTA)
+                };
+                Vec3::make_vec3(
+                    -scale * slope(BUMP_DELTA, 0.0),
+                    -scale * slope(0.0, BUMP_DELTA),
+                    1.0,
+                )
+            }
+        };
+        frame.to_world(local).normalize()
+    }
+}
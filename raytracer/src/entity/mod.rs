@@ -3,13 +3,26 @@
 #![allow(unused_variables)]
 //------------------------------    Modules    -----------------------------------------
 
+pub mod aabb;
+pub mod bezier;
+pub mod csg;
 pub mod material;
+pub mod sdf;
+pub mod shape;
+pub mod volume;
 
+use crate::entity::aabb::Aabb;
+use crate::entity::bezier::BezierMesh;
+use crate::entity::csg::Csg;
 use crate::entity::material::*;
 use crate::graphics::ray;
 use crate::math_support::*;
 use crate::DEFAULT_COLOR;
 use ray::Ray;
+use sdf::Implicit;
+use shape::{Capsule, Cone, Cylinder, Torus};
+use std::f64::consts::PI;
+use volume::Volume;
 
 //--------------------------------------------------------------------------------------
 // Trait CanHit
@@ -23,36 +36,100 @@ pub trait CanHit {
     }
 
     fn get_hit_normal(&self, pos: Vec3) -> Vec3;
+
+    fn get_uv(&self, pos: Vec3) -> (f64, f64) {
+        // Surface coordinates for textures.
+        (0.0, 0.0)
+    }
+
+    fn get_tangent(&self, pos: Vec3) -> Vec3 {
+        // Direction of increasing u, which orients the maps in tangent space.
+        make_onb(self.get_hit_normal(pos)).0
+    }
+
+    fn get_surface(&self, pos: Vec3) -> (Vec3, (f64, f64), Vec3) {
+        // Normal, uv and tangent at once, for objs which find all three by the same search.
+        (
+            self.get_hit_normal(pos),
+            self.get_uv(pos),
+            self.get_tangent(pos),
+        )
+    }
+
+    fn get_bounding_box(&self) -> Option<Aabb> {
+        None // Unbounded.
+    }
+
+    fn get_area(&self) -> f64 {
+        0.0
+    }
+
+    fn get_normal_cone(&self) -> (Vec3, f64) {
+        // Axis and half angle bounding all normals.
+        (Vec3::make_vec3(0.0, 1.0, 0.0), PI)
+    }
+
+    fn sample_dir_from(&self, pos: Vec3) -> (Vec3, f64) {
+        // Direction from pos towards a point on the surface, and its solid angle pdf.
+        (Vec3::make_vec3(0.0, 1.0, 0.0), 0.0)
+    }
+
+    fn get_pdf_from(&self, pos: Vec3, dir: Vec3) -> f64 {
+        0.0
+    }
+
+    fn get_hit_intervals(&self, target_ray: &Ray) -> Vec<(f64, f64)> {
+        // Times the ray is inside, along the whole line and in order.
+        // Empty if the obj does not enclose a solid.
+        vec![]
+    }
+
+    fn get_transmittance(&self, target_ray: &Ray, dist: f64) -> f64 {
+        // Fraction of light passing within dist along the ray.
+        let tm = self.get_hit_time(target_ray);
+        if tm >= EPS && tm < dist {
+            0.0
+        } else {
+            1.0
+        }
+    }
+}
+
+pub fn get_intervals(mut times: Vec<f64>) -> Vec<(f64, f64)> {
+    // Pairs the times a ray crosses a closed surface into the intervals inside.
+    times.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    times
+        .chunks(2)
+        .filter(|pair| pair.len() == 2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect()
 }
 
 //------------------------    Struct Plain    ------------------------------------------
 pub struct Plain {
-    // A xOz plain served as a background.
-    y: f64,
+    // An infinite plain through pos, which can be hit from both sides.
+    pos: Vec3,
+    normal: Vec3,
     pub material: Mat,
 }
 
 impl Plain {
-    pub fn make_plain(y: f64, material: Mat) -> Plain {
-        Plain { y, material }
+    pub fn make_plain(pos: Vec3, normal: Vec3, material: Mat) -> Plain {
+        Plain {
+            pos,
+            normal: normal.normalize(),
+            material,
+        }
     }
 }
 
 impl CanHit for Plain {
     fn get_hit_time(&self, target_ray: &ray::Ray) -> f64 {
-        let product: f64 = dot(
-            target_ray.get_dir(),
-            Vec3 {
-                x: 0.0,
-                y: -1.0,
-                z: 0.0,
-            },
-        );
-        if product < 0.0 {
-            -1.0
+        let product: f64 = dot(target_ray.get_dir(), self.normal);
+        if close_to(product, 0.0) {
+            -1.0 // Parallel to the plain.
         } else {
-            let y_spd = -target_ray.get_dir().y;
-            (target_ray.get_pos().y - self.y) / y_spd
+            dot(self.pos - target_ray.get_pos(), self.normal) / product
         }
     }
 
@@ -61,7 +138,112 @@ impl CanHit for Plain {
     }
 
     fn get_hit_normal(&self, pos: Vec3) -> Vec3 {
-        Vec3::make_vec3(0.0, 1.0, 0.0)
+        self.normal
+    }
+
+    fn get_uv(&self, pos: Vec3) -> (f64, f64) {
+        // In world units from pos, so textures tile once per unit.
+        let (t, b) = make_onb(self.normal);
+        (dot(pos - self.pos, t), dot(pos - self.pos, b))
+    }
+
+    fn get_tangent(&self, pos: Vec3) -> Vec3 {
+        make_onb(self.normal).0
+    }
+}
+
+//-------------------------    Struct Disk    ------------------------------------------
+
+pub struct Disk {
+    centre: Vec3,
+    normal: Vec3,
+    r: f64,
+    pub material: Mat,
+}
+
+impl Disk {
+    pub fn make_disk(centre: Vec3, normal: Vec3, r: f64, material: Mat) -> Disk {
+        Disk {
+            centre,
+            normal: normal.normalize(),
+            r,
+            material,
+        }
+    }
+    pub fn get_centre(&self) -> Vec3 {
+        self.centre
+    }
+    pub fn get_radius(&self) -> f64 {
+        self.r
+    }
+}
+
+impl CanHit for Disk {
+    fn get_hit_time(&self, target_ray: &Ray) -> f64 {
+        let product: f64 = dot(target_ray.get_dir(), self.normal);
+        if close_to(product, 0.0) {
+            return -1.0;
+        }
+        let tm = dot(self.centre - target_ray.get_pos(), self.normal) / product;
+        let hit_pos: Vec3 = target_ray.get_pos() + tm * target_ray.get_dir();
+        if (hit_pos - self.centre).get_len() > self.r {
+            -1.0 // Outside the disk.
+        } else {
+            tm
+        }
+    }
+
+    fn get_hit_normal(&self, pos: Vec3) -> Vec3 {
+        self.normal
+    }
+
+    fn get_uv(&self, pos: Vec3) -> (f64, f64) {
+        // Over the disk from 0 to 1.
+        let (t, b) = make_onb(self.normal);
+        let d = (pos - self.centre) / (2.0 * self.r);
+        (dot(d, t) + 0.5, dot(d, b) + 0.5)
+    }
+
+    fn get_tangent(&self, pos: Vec3) -> Vec3 {
+        make_onb(self.normal).0
+    }
+
+    fn get_bounding_box(&self) -> Option<Aabb> {
+        let extent = |n: f64| self.r * (1.0 - n * n).max(0.0).sqrt();
+        let half = Vec3::make_vec3(
+            extent(self.normal.x),
+            extent(self.normal.y),
+            extent(self.normal.z),
+        );
+        Some(Aabb::make_aabb(self.centre - half, self.centre + half))
+    }
+
+    fn get_area(&self) -> f64 {
+        PI * self.r * self.r
+    }
+
+    fn get_normal_cone(&self) -> (Vec3, f64) {
+        (self.normal, 0.0)
+    }
+
+    fn sample_dir_from(&self, pos: Vec3) -> (Vec3, f64) {
+        // Uniform on the area.
+        let (t, b) = make_onb(self.normal);
+        let r = self.r * rand_0_1().sqrt();
+        let phi = 2.0 * PI * rand_0_1();
+        let point = self.centre + (r * phi.cos()) * t + (r * phi.sin()) * b;
+        let dir = (point - pos).normalize();
+        (dir, self.get_pdf_from(pos, dir))
+    }
+
+    fn get_pdf_from(&self, pos: Vec3, dir: Vec3) -> f64 {
+        let tm = self.get_hit_time(&Ray::make_ray(pos, dir));
+        let cos_theta = dot(dir.normalize(), self.normal).abs();
+        if tm < EPS || cos_theta < EPS {
+            return 0.0;
+        }
+        // From area measure to solid angle.
+        tm * tm / (cos_theta * self.get_area())
     }
 }
 
@@ -98,14 +280,18 @@ impl CanHit for Sphere {
 
         let delta = b * b - c;
         if delta < 0.0 {
-            -1.0 // Not hit.
+            return -1.0; // Not hit.
+        }
+        let tm = -b - delta.sqrt(); // First hit time.
+        if tm < EPS {
+            -b + delta.sqrt() // From the inside.
         } else {
-            -b - delta.sqrt() // First hit time.
+            tm
         }
     }
 
     fn get_hit_color(&self, target_ray: &Ray) -> Vec3 {
-        let tm: f64 = self.get_hit_time(&target_ray);
+        let tm: f64 = self.get_hit_time(target_ray);
         let hit_pos: Vec3 = target_ray.get_pos() + tm * target_ray.get_dir();
         let hit_pos = hit_pos - self.centre;
         hit_pos.normalize() // As the color of the surface.
@@ -115,6 +301,65 @@ impl CanHit for Sphere {
         let op: Vec3 = pos - self.centre;
         op.normalize()
     }
+
+    fn get_uv(&self, pos: Vec3) -> (f64, f64) {
+        // u around the y axis, v from the bottom pole to the top one.
+        let p = (pos - self.centre).normalize();
+        let u = ((-p.z).atan2(p.x) + PI) / (2.0 * PI);
+        let v = (-p.y).clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    fn get_tangent(&self, pos: Vec3) -> Vec3 {
+        let p = pos - self.centre;
+        let tangent = Vec3::make_vec3(p.z, 0.0, -p.x);
+        if tangent.get_len() < EPS {
+            make_onb(p.normalize()).0 // At the poles.
+        } else {
+            tangent.normalize()
+        }
+    }
+
+    fn get_hit_intervals(&self, target_ray: &Ray) -> Vec<(f64, f64)> {
+        let oc: Vec3 = target_ray.get_pos() - self.centre;
+        let b = dot(oc, target_ray.get_dir());
+        let delta = b * b - (dot(oc, oc) - self.r * self.r);
+        if delta < 0.0 {
+            return vec![];
+        }
+        vec![(-b - delta.sqrt(), -b + delta.sqrt())]
+    }
+
+    fn get_bounding_box(&self) -> Option<Aabb> {
+        let half = Vec3::make_vec3(self.r, self.r, self.r);
+        Some(Aabb::make_aabb(self.centre - half, self.centre + half))
+    }
+
+    fn get_area(&self) -> f64 {
+        4.0 * PI * self.r * self.r
+    }
+
+    fn sample_dir_from(&self, pos: Vec3) -> (Vec3, f64) {
+        // Uniform in the cone the sphere subtends.
+        let oc: Vec3 = self.centre - pos;
+        let dist2 = dot(oc, oc);
+        if dist2 <= self.r * self.r {
+            return (rand_normalized_vec(), 0.0); // Inside, cannot be seen as a light.
+        }
+        let cos_max = (1.0 - self.r * self.r / dist2).sqrt();
+        let dir = sample_cone(oc.normalize(), cos_max);
+        (dir, 1.0 / (2.0 * PI * (1.0 - cos_max)))
+    }
+
+    fn get_pdf_from(&self, pos: Vec3, dir: Vec3) -> f64 {
+        let oc: Vec3 = self.centre - pos;
+        let dist2 = dot(oc, oc);
+        if dist2 <= self.r * self.r || self.get_hit_time(&Ray::make_ray(pos, dir)) < EPS {
+            return 0.0;
+        }
+        let cos_max = (1.0 - self.r * self.r / dist2).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_max))
+    }
 }
 
 //-------------------------------    Enum Entity    --------------------------------------
@@ -122,7 +367,16 @@ impl CanHit for Sphere {
 pub enum Entity {
     None,
     Pln(Plain),
+    Dsk(Disk),
     Sph(Sphere),
+    Vol(Volume),
+    Cyl(Cylinder),
+    Con(Cone),
+    Tor(Torus),
+    Cap(Capsule),
+    Imp(Implicit),
+    Csg(Csg),
+    Bez(BezierMesh),
 }
 
 impl Entity {
@@ -130,10 +384,36 @@ impl Entity {
         Entity::None
     }
 
+    pub fn get_material(&self) -> Option<&Mat> {
+        match self {
+            Entity::Pln(tmp) => Some(&tmp.material),
+            Entity::Dsk(tmp) => Some(&tmp.material),
+            Entity::Sph(tmp) => Some(&tmp.material),
+            Entity::Vol(tmp) => Some(&tmp.material),
+            Entity::Cyl(tmp) => Some(&tmp.material),
+            Entity::Con(tmp) => Some(&tmp.material),
+            Entity::Tor(tmp) => Some(&tmp.material),
+            Entity::Cap(tmp) => Some(&tmp.material),
+            Entity::Imp(tmp) => Some(&tmp.material),
+            Entity::Csg(tmp) => Some(&tmp.material),
+            Entity::Bez(tmp) => Some(&tmp.material),
+            _ => None,
+        }
+    }
+
     pub fn scatter(&self, target_ray: &Ray, normal: Vec3) -> Ray {
         match self {
             Entity::Pln(tmp) => tmp.material.scatter(target_ray, normal),
+            Entity::Dsk(tmp) => tmp.material.scatter(target_ray, normal),
             Entity::Sph(tmp) => tmp.material.scatter(target_ray, normal),
+            Entity::Vol(tmp) => tmp.material.scatter(target_ray, normal),
+            Entity::Cyl(tmp) => tmp.material.scatter(target_ray, normal),
+            Entity::Con(tmp) => tmp.material.scatter(target_ray, normal),
+            Entity::Tor(tmp) => tmp.material.scatter(target_ray, normal),
+            Entity::Cap(tmp) => tmp.material.scatter(target_ray, normal),
+            Entity::Imp(tmp) => tmp.material.scatter(target_ray, normal),
+            Entity::Csg(tmp) => tmp.material.scatter(target_ray, normal),
+            Entity::Bez(tmp) => tmp.material.scatter(target_ray, normal),
             _ => Ray::make_ray(crate::origin, Vec3::make_vec3(0.0, 1.0, 0.0)),
         }
     }
@@ -141,7 +421,16 @@ impl Entity {
     pub fn get_albedo(&self) -> Vec3 {
         match self {
             Entity::Pln(tmp) => tmp.material.get_albedo(),
+            Entity::Dsk(tmp) => tmp.material.get_albedo(),
             Entity::Sph(tmp) => tmp.material.get_albedo(),
+            Entity::Vol(tmp) => tmp.material.get_albedo(),
+            Entity::Cyl(tmp) => tmp.material.get_albedo(),
+            Entity::Con(tmp) => tmp.material.get_albedo(),
+            Entity::Tor(tmp) => tmp.material.get_albedo(),
+            Entity::Cap(tmp) => tmp.material.get_albedo(),
+            Entity::Imp(tmp) => tmp.material.get_albedo(),
+            Entity::Csg(tmp) => tmp.material.get_albedo(),
+            Entity::Bez(tmp) => tmp.material.get_albedo(),
             _ => Vec3::make_vec3(0.0, 0.0, 0.0),
         }
     }
@@ -151,7 +440,16 @@ impl CanHit for Entity {
     fn get_hit_time(&self, target_ray: &Ray) -> f64 {
         match self {
             Entity::Pln(tmp) => tmp.get_hit_time(target_ray),
+            Entity::Dsk(tmp) => tmp.get_hit_time(target_ray),
             Entity::Sph(tmp) => tmp.get_hit_time(target_ray),
+            Entity::Vol(tmp) => tmp.get_hit_time(target_ray),
+            Entity::Cyl(tmp) => tmp.get_hit_time(target_ray),
+            Entity::Con(tmp) => tmp.get_hit_time(target_ray),
+            Entity::Tor(tmp) => tmp.get_hit_time(target_ray),
+            Entity::Cap(tmp) => tmp.get_hit_time(target_ray),
+            Entity::Imp(tmp) => tmp.get_hit_time(target_ray),
+            Entity::Csg(tmp) => tmp.get_hit_time(target_ray),
+            Entity::Bez(tmp) => tmp.get_hit_time(target_ray),
             _ => -1.0,
         }
     }
@@ -159,7 +457,16 @@ impl CanHit for Entity {
     fn get_hit_color(&self, target_ray: &Ray) -> Vec3 {
         match self {
             Entity::Pln(tmp) => tmp.get_hit_color(target_ray),
+            Entity::Dsk(tmp) => tmp.get_hit_color(target_ray),
             Entity::Sph(tmp) => tmp.get_hit_color(target_ray),
+            Entity::Vol(tmp) => tmp.get_hit_color(target_ray),
+            Entity::Cyl(tmp) => tmp.get_hit_color(target_ray),
+            Entity::Con(tmp) => tmp.get_hit_color(target_ray),
+            Entity::Tor(tmp) => tmp.get_hit_color(target_ray),
+            Entity::Cap(tmp) => tmp.get_hit_color(target_ray),
+            Entity::Imp(tmp) => tmp.get_hit_color(target_ray),
+            Entity::Csg(tmp) => tmp.get_hit_color(target_ray),
+            Entity::Bez(tmp) => tmp.get_hit_color(target_ray),
             _ => crate::DEFAULT_COLOR,
         }
     }
@@ -167,8 +474,191 @@ impl CanHit for Entity {
     fn get_hit_normal(&self, pos: Vec3) -> Vec3 {
         match self {
             Entity::Pln(tmp) => tmp.get_hit_normal(pos),
+            Entity::Dsk(tmp) => tmp.get_hit_normal(pos),
             Entity::Sph(tmp) => tmp.get_hit_normal(pos),
+            Entity::Vol(tmp) => tmp.get_hit_normal(pos),
+            Entity::Cyl(tmp) => tmp.get_hit_normal(pos),
+            Entity::Con(tmp) => tmp.get_hit_normal(pos),
+            Entity::Tor(tmp) => tmp.get_hit_normal(pos),
+            Entity::Cap(tmp) => tmp.get_hit_normal(pos),
+            Entity::Imp(tmp) => tmp.get_hit_normal(pos),
+            Entity::Csg(tmp) => tmp.get_hit_normal(pos),
+            Entity::Bez(tmp) => tmp.get_hit_normal(pos),
             _ => Vec3::make_vec3(0.0, 1.0, 0.0),
         }
     }
+
+    fn get_uv(&self, pos: Vec3) -> (f64, f64) {
+        match self {
+            Entity::Pln(tmp) => tmp.get_uv(pos),
+            Entity::Dsk(tmp) => tmp.get_uv(pos),
+            Entity::Sph(tmp) => tmp.get_uv(pos),
+            Entity::Vol(tmp) => tmp.get_uv(pos),
+            Entity::Cyl(tmp) => tmp.get_uv(pos),
+            Entity::Con(tmp) => tmp.get_uv(pos),
+            Entity::Tor(tmp) => tmp.get_uv(pos),
+            Entity::Cap(tmp) => tmp.get_uv(pos),
+            Entity::Imp(tmp) => tmp.get_uv(pos),
+            Entity::Csg(tmp) => tmp.get_uv(pos),
+            Entity::Bez(tmp) => tmp.get_uv(pos),
+            _ => (0.0, 0.0),
+        }
+    }
+
+    fn get_tangent(&self, pos: Vec3) -> Vec3 {
+        match self {
+            Entity::Pln(tmp) => tmp.get_tangent(pos),
+            Entity::Dsk(tmp) => tmp.get_tangent(pos),
+            Entity::Sph(tmp) => tmp.get_tangent(pos),
+            Entity::Vol(tmp) => tmp.get_tangent(pos),
+            Entity::Cyl(tmp) => tmp.get_tangent(pos),
+            Entity::Con(tmp) => tmp.get_tangent(pos),
+            Entity::Tor(tmp) => tmp.get_tangent(pos),
+            Entity::Cap(tmp) => tmp.get_tangent(pos),
+            Entity::Imp(tmp) => tmp.get_tangent(pos),
+            Entity::Csg(tmp) => tmp.get_tangent(pos),
+            Entity::Bez(tmp) => tmp.get_tangent(pos),
+            _ => Vec3::make_vec3(1.0, 0.0, 0.0),
+        }
+    }
+
+    fn get_surface(&self, pos: Vec3) -> (Vec3, (f64, f64), Vec3) {
+        match self {
+            Entity::Pln(tmp) => tmp.get_surface(pos),
+            Entity::Dsk(tmp) => tmp.get_surface(pos),
+            Entity::Sph(tmp) => tmp.get_surface(pos),
+            Entity::Vol(tmp) => tmp.get_surface(pos),
+            Entity::Cyl(tmp) => tmp.get_surface(pos),
+            Entity::Con(tmp) => tmp.get_surface(pos),
+            Entity::Tor(tmp) => tmp.get_surface(pos),
+            Entity::Cap(tmp) => tmp.get_surface(pos),
+            Entity::Imp(tmp) => tmp.get_surface(pos),
+            Entity::Csg(tmp) => tmp.get_surface(pos),
+            Entity::Bez(tmp) => tmp.get_surface(pos),
+            _ => (
+                Vec3::make_vec3(0.0, 1.0, 0.0),
+                (0.0, 0.0),
+                Vec3::make_vec3(1.0, 0.0, 0.0),
+            ),
+        }
+    }
+
+    fn get_bounding_box(&self) -> Option<Aabb> {
+        match self {
+            Entity::Pln(tmp) => tmp.get_bounding_box(),
+            Entity::Dsk(tmp) => tmp.get_bounding_box(),
+            Entity::Sph(tmp) => tmp.get_bounding_box(),
+            Entity::Vol(tmp) => tmp.get_bounding_box(),
+            Entity::Cyl(tmp) => tmp.get_bounding_box(),
+            Entity::Con(tmp) => tmp.get_bounding_box(),
+            Entity::Tor(tmp) => tmp.get_bounding_box(),
+            Entity::Cap(tmp) => tmp.get_bounding_box(),
+            Entity::Imp(tmp) => tmp.get_bounding_box(),
+            Entity::Csg(tmp) => tmp.get_bounding_box(),
+            Entity::Bez(tmp) => tmp.get_bounding_box(),
+            _ => None,
+        }
+    }
+
+    fn get_area(&self) -> f64 {
+        match self {
+            Entity::Pln(tmp) => tmp.get_area(),
+            Entity::Dsk(tmp) => tmp.get_area(),
+            Entity::Sph(tmp) => tmp.get_area(),
+            Entity::Vol(tmp) => tmp.get_area(),
+            Entity::Cyl(tmp) => tmp.get_area(),
+            Entity::Con(tmp) => tmp.get_area(),
+            Entity::Tor(tmp) => tmp.get_area(),
+            Entity::Cap(tmp) => tmp.get_area(),
+            Entity::Imp(tmp) => tmp.get_area(),
+            Entity::Csg(tmp) => tmp.get_area(),
+            Entity::Bez(tmp) => tmp.get_area(),
+            _ => 0.0,
+        }
+    }
+
+    fn get_normal_cone(&self) -> (Vec3, f64) {
+        match self {
+            Entity::Pln(tmp) => tmp.get_normal_cone(),
+            Entity::Dsk(tmp) => tmp.get_normal_cone(),
+            Entity::Sph(tmp) => tmp.get_normal_cone(),
+            Entity::Vol(tmp) => tmp.get_normal_cone(),
+            Entity::Cyl(tmp) => tmp.get_normal_cone(),
+            Entity::Con(tmp) => tmp.get_normal_cone(),
+            Entity::Tor(tmp) => tmp.get_normal_cone(),
+            Entity::Cap(tmp) => tmp.get_normal_cone(),
+            Entity::Imp(tmp) => tmp.get_normal_cone(),
+            Entity::Csg(tmp) => tmp.get_normal_cone(),
+            Entity::Bez(tmp) => tmp.get_normal_cone(),
+            _ => (Vec3::make_vec3(0.0, 1.0, 0.0), PI),
+        }
+    }
+
+    fn sample_dir_from(&self, pos: Vec3) -> (Vec3, f64) {
+        match self {
+            Entity::Pln(tmp) => tmp.sample_dir_from(pos),
+            Entity::Dsk(tmp) => tmp.sample_dir_from(pos),
+            Entity::Sph(tmp) => tmp.sample_dir_from(pos),
+            Entity::Vol(tmp) => tmp.sample_dir_from(pos),
+            Entity::Cyl(tmp) => tmp.sample_dir_from(pos),
+            Entity::Con(tmp) => tmp.sample_dir_from(pos),
+            Entity::Tor(tmp) => tmp.sample_dir_from(pos),
+            Entity::Cap(tmp) => tmp.sample_dir_from(pos),
+            Entity::Imp(tmp) => tmp.sample_dir_from(pos),
+            Entity::Csg(tmp) => tmp.sample_dir_from(pos),
+            Entity::Bez(tmp) => tmp.sample_dir_from(pos),
+            _ => (Vec3::make_vec3(0.0, 1.0, 0.0), 0.0),
+        }
+    }
+
+    fn get_pdf_from(&self, pos: Vec3, dir: Vec3) -> f64 {
+        match self {
+            Entity::Pln(tmp) => tmp.get_pdf_from(pos, dir),
+            Entity::Dsk(tmp) => tmp.get_pdf_from(pos, dir),
+            Entity::Sph(tmp) => tmp.get_pdf_from(pos, dir),
+            Entity::Vol(tmp) => tmp.get_pdf_from(pos, dir),
+            Entity::Cyl(tmp) => tmp.get_pdf_from(pos, dir),
+            Entity::Con(tmp) => tmp.get_pdf_from(pos, dir),
+            Entity::Tor(tmp) => tmp.get_pdf_from(pos, dir),
+            Entity::Cap(tmp) => tmp.get_pdf_from(pos, dir),
+            Entity::Imp(tmp) => tmp.get_pdf_from(pos, dir),
+            Entity::Csg(tmp) => tmp.get_pdf_from(pos, dir),
+            Entity::Bez(tmp) => tmp.get_pdf_from(pos, dir),
+            _ => 0.0,
+        }
+    }
+
+    fn get_hit_intervals(&self, target_ray: &Ray) -> Vec<(f64, f64)> {
+        match self {
+            Entity::Pln(tmp) => tmp.get_hit_intervals(target_ray),
+            Entity::Dsk(tmp) => tmp.get_hit_intervals(target_ray),
+            Entity::Sph(tmp) => tmp.get_hit_intervals(target_ray),
+            Entity::Vol(tmp) => tmp.get_hit_intervals(target_ray),
+            Entity::Cyl(tmp) => tmp.get_hit_intervals(target_ray),
+            Entity::Con(tmp) => tmp.get_hit_intervals(target_ray),
+            Entity::Tor(tmp) => tmp.get_hit_intervals(target_ray),
+            Entity::Cap(tmp) => tmp.get_hit_intervals(target_ray),
+            Entity::Imp(tmp) => tmp.get_hit_intervals(target_ray),
+            Entity::Csg(tmp) => tmp.get_hit_intervals(target_ray),
+            Entity::Bez(tmp) => tmp.get_hit_intervals(target_ray),
+            _ => vec![],
+        }
+    }
+
+    fn get_transmittance(&self, target_ray: &Ray, dist: f64) -> f64 {
+        match self {
+            Entity::Pln(tmp) => tmp.get_transmittance(target_ray, dist),
+            Entity::Dsk(tmp) => tmp.get_transmittance(target_ray, dist),
+            Entity::Sph(tmp) => tmp.get_transmittance(target_ray, dist),
+            Entity::Vol(tmp) => tmp.get_transmittance(target_ray, dist),
+            Entity::Cyl(tmp) => tmp.get_transmittance(target_ray, dist),
+            Entity::Con(tmp) => tmp.get_transmittance(target_ray, dist),
+            Entity::Tor(tmp) => tmp.get_transmittance(target_ray, dist),
+            Entity::Cap(tmp) => tmp.get_transmittance(target_ray, dist),
+            Entity::Imp(tmp) => tmp.get_transmittance(target_ray, dist),
+            Entity::Csg(tmp) => tmp.get_transmittance(target_ray, dist),
+            Entity::Bez(tmp) => tmp.get_transmittance(target_ray, dist),
+            _ => 1.0,
+        }
+    }
 }
@@ -0,0 +1,204 @@
+//---------------------------------    Mod Sdf    ----------------------------------------
+// Surfaces where a signed distance function is 0, negative inside. They are intersected
+// by sphere tracing, stepping along the ray by the distance to the surface, which is
+// safe as long as the function never overestimates the distance.
+use crate::entity::aabb::Aabb;
+use crate::entity::material::*;
+use crate::entity::CanHit;
+use crate::graphics::ray::Ray;
+use crate::math_support::*;
+
+const MAX_STEPS: i32 = 512;
+const HIT_DIST: f64 = 0.00001; // The surface is where the distance gets below.
+const START_DIST: f64 = 0.0001; // Rays leaving the surface start marching beyond.
+const NORMAL_DELTA: f64 = 0.00001;
+
+//-------------------------------    Enum Sdf    ------------------------------------------
+
+pub enum Sdf {
+    // Primitives, centred at the origin.
+    Sphere(f64),
+    Box(Vec3),            // Half extents.
+    RoundBox(Vec3, f64),  // Half extents, with the edges rounded by the radius.
+    Torus(f64, f64),      // Major and minor radius, around the y axis.
+    Mandelbulb(f64, i32), // Power and iterations, inside the ball of radius 1.2.
+    // Operations.
+    Translate(Vec3, Box<Sdf>),
+    Scale(f64, Box<Sdf>),
+    SmoothUnion(Box<Sdf>, Box<Sdf>, f64), // Blended within the distance.
+    Subtract(Box<Sdf>, Box<Sdf>),         // The first without the second.
+    Repeat(Vec3, Box<Sdf>), // Infinitely, with the period along each axis, 0 for none.
+    Twist(f64, Box<Sdf>),   // Around the y axis, in radians per unit length.
+}
+
+impl Sdf {
+    pub fn make_translate(offset: Vec3, sdf: Sdf) -> Sdf {
+        Sdf::Translate(offset, Box::new(sdf))
+    }
+
+    pub fn make_scale(scale: f64, sdf: Sdf) -> Sdf {
+        Sdf::Scale(scale, Box::new(sdf))
+    }
+
+    pub fn make_smooth_union(a: Sdf, b: Sdf, k: f64) -> Sdf {
+        Sdf::SmoothUnion(Box::new(a), Box::new(b), k)
+    }
+
+    pub fn make_subtract(a: Sdf, b: Sdf) -> Sdf {
+        Sdf::Subtract(Box::new(a), Box::new(b))
+    }
+
+    pub fn make_repeat(period: Vec3, sdf: Sdf) -> Sdf {
+        Sdf::Repeat(period, Box::new(sdf))
+    }
+
+    pub fn make_twist(rate: f64, sdf: Sdf) -> Sdf {
+        Sdf::Twist(rate, Box::new(sdf))
+    }
+
+    pub fn get_dist(&self, p: Vec3) -> f64 {
+        match self {
+            Sdf::Sphere(r) => p.get_len() - r,
+            Sdf::Box(half) => get_box_dist(p, *half),
+            Sdf::RoundBox(half, r) => {
+                let inner = *half - Vec3::make_vec3(*r, *r, *r);
+                get_box_dist(p, inner.max_with(0.0)) - r
+            }
+            Sdf::Torus(major, minor) => {
+                let ring = (p.x * p.x + p.z * p.z).sqrt() - major;
+                (ring * ring + p.y * p.y).sqrt() - minor
+            }
+            Sdf::Mandelbulb(power, iterations) => get_mandelbulb_dist(p, *power, *iterations),
+            Sdf::Translate(offset, sdf) => sdf.get_dist(p - *offset),
+            Sdf::Scale(scale, sdf) => scale * sdf.get_dist(p / *scale),
+            Sdf::SmoothUnion(a, b, k) => {
+                let (da, db) = (a.get_dist(p), b.get_dist(p));
+                if *k <= 0.0 {
+                    return da.min(db);
+                }
+                let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+                db * (1.0 - h) + da * h - k * h * (1.0 - h)
+            }
+            Sdf::Subtract(a, b) => a.get_dist(p).max(-b.get_dist(p)),
+            Sdf::Repeat(period, sdf) => {
+                let wrap = |val: f64, period: f64| {
+                    if period > 0.0 {
+                        val - period * (val / period).round()
+                    } else {
+                        val
+                    }
+                };
+                sdf.get_dist(Vec3::make_vec3(
+                    wrap(p.x, period.x),
+                    wrap(p.y, period.y),
+                    wrap(p.z, period.z),
+                ))
+            }
+            Sdf::Twist(rate, sdf) => {
+                let (s, c) = (rate * p.y).sin_cos();
+                sdf.get_dist(Vec3::make_vec3(c * p.x - s * p.z, p.y, s * p.x + c * p.z))
+            }
+        }
+    }
+}
+
+fn get_box_dist(p: Vec3, half: Vec3) -> f64 {
+    let q = Vec3::make_vec3(p.x.abs(), p.y.abs(), p.z.abs()) - half;
+    q.max_with(0.0).get_len() + q.get_max().min(0.0)
+}
+
+fn get_mandelbulb_dist(p: Vec3, power: f64, iterations: i32) -> f64 {
+    // Distance estimate from the running derivative of z -> z^power + p in spherical form.
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = z.get_len();
+    for _ in 0..iterations {
+        if !(EPS..=2.0).contains(&r) {
+            break;
+        }
+        let theta = (z.z / r).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        dr = power * r.powf(power - 1.0) * dr + 1.0;
+        let zr = r.powf(power);
+        z =
+            zr * Vec3::make_vec3(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            ) + p;
+        r = z.get_len();
+    }
+    if r < EPS {
+        return 0.0;
+    }
+    0.5 * r.ln() * r / dr
+}
+
+//------------------------------    Struct Implicit    -----------------------------------
+
+pub struct Implicit {
+    sdf: Sdf,
+    bbox: Aabb, // Only marched inside.
+    step: f64,  // Fraction of the distance stepped, below 1 for twists and other bent spaces.
+    pub material: Mat,
+}
+
+impl Implicit {
+    pub fn make_implicit(sdf: Sdf, bbox: Aabb, step: f64, material: Mat) -> Implicit {
+        Implicit {
+            sdf,
+            bbox,
+            step: step.clamp(0.05, 1.0),
+            material,
+        }
+    }
+}
+
+impl CanHit for Implicit {
+    fn get_hit_time(&self, target_ray: &Ray) -> f64 {
+        let (t0, t1) = match self.bbox.get_hit_range(target_ray) {
+            Some(range) => range,
+            None => return -1.0,
+        };
+        let at = |tm: f64| target_ray.get_pos() + tm * target_ray.get_dir();
+        let mut tm = t0.max(START_DIST);
+        // Marches outside to in, or inside to out for rays inside the surface.
+        let side = if self.sdf.get_dist(at(tm)) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        for _ in 0..MAX_STEPS {
+            if tm > t1 {
+                return -1.0;
+            }
+            let dist = side * self.sdf.get_dist(at(tm));
+            if dist < HIT_DIST {
+                return tm;
+            }
+            tm += self.step * dist;
+        }
+        -1.0
+    }
+
+    fn get_hit_normal(&self, pos: Vec3) -> Vec3 {
+        // Gradient by the tetrahedron of differences.
+        let k = [
+            Vec3::make_vec3(1.0, -1.0, -1.0),
+            Vec3::make_vec3(-1.0, -1.0, 1.0),
+            Vec3::make_vec3(-1.0, 1.0, -1.0),
+            Vec3::make_vec3(1.0, 1.0, 1.0),
+        ];
+        let gradient = k.iter().fold(Vec3::make_vec3(0.0, 0.0, 0.0), |res, k| {
+            res + self.sdf.get_dist(pos + NORMAL_DELTA * *k) * *k
+        });
+        if gradient.get_len() < EPS {
+            return Vec3::make_vec3(0.0, 1.0, 0.0);
+        }
+        gradient.normalize()
+    }
+
+    fn get_bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
@@ -0,0 +1,439 @@
+//--------------------------------    Mod Shape    ---------------------------------------
+// Analytic shapes of revolution around an axis: cylinders, cones, tori and capsules.
+// Each is intersected in its own frame, whose z is the axis and whose origin is the
+// centre of the base (of the torus, its centre).
+use crate::entity::aabb::Aabb;
+use crate::entity::material::microfacet::Frame;
+use crate::entity::material::*;
+use crate::entity::{get_intervals, CanHit};
+use crate::graphics::ray::Ray;
+use crate::math_support::*;
+use std::f64::consts::PI;
+
+fn get_first_time(times: &[f64]) -> f64 {
+    // The earliest time in front of the ray, -1 if none.
+    times.iter().filter(|tm| **tm >= EPS).fold(
+        -1.0,
+        |res, tm| if res < 0.0 || *tm < res { *tm } else { res },
+    )
+}
+
+fn get_angle_u(local: Vec3) -> f64 {
+    // Around the axis, from 0 to 1.
+    local.y.atan2(local.x) / (2.0 * PI) + 0.5
+}
+
+fn get_tangent_around(axis: Vec3, normal: Vec3) -> Vec3 {
+    // Direction of increasing angle around the axis, any where the normal is the axis.
+    let tangent = cross(axis, normal);
+    if tangent.get_len() < EPS {
+        make_onb(normal).0
+    } else {
+        tangent.normalize()
+    }
+}
+
+fn get_disk_box(centre: Vec3, axis: Vec3, r: f64) -> Aabb {
+    // Bounds a circle of radius r around the axis.
+    let extent = |n: f64| r * (1.0 - n * n).max(0.0).sqrt();
+    let half = Vec3::make_vec3(extent(axis.x), extent(axis.y), extent(axis.z));
+    Aabb::make_aabb(centre - half, centre + half)
+}
+
+//------------------------------    Struct Cylinder    -----------------------------------
+
+pub struct Cylinder {
+    centre: Vec3, // Of the bottom cap.
+    frame: Frame,
+    r: f64,
+    h: f64,
+    capped: bool, // A tube if not.
+    pub material: Mat,
+}
+
+impl Cylinder {
+    pub fn make_cylinder(
+        centre: Vec3,
+        axis: Vec3,
+        r: f64,
+        h: f64,
+        capped: bool,
+        material: Mat,
+    ) -> Cylinder {
+        Cylinder {
+            centre,
+            frame: Frame::make_frame(axis),
+            r,
+            h,
+            capped,
+            material,
+        }
+    }
+
+    fn get_crossings(&self, target_ray: &Ray) -> Vec<f64> {
+        let o = self.frame.to_local(target_ray.get_pos() - self.centre);
+        let d = self.frame.to_local(target_ray.get_dir());
+        let mut times = Vec::new();
+        for tm in solve_quadratic(
+            d.x * d.x + d.y * d.y,
+            2.0 * (o.x * d.x + o.y * d.y),
+            o.x * o.x + o.y * o.y - self.r * self.r,
+        ) {
+            let z = o.z + tm * d.z;
+            if z >= 0.0 && z <= self.h {
+                times.push(tm);
+            }
+        }
+        if self.capped && d.z.abs() > EPS {
+            for z in [0.0, self.h].iter() {
+                let tm = (z - o.z) / d.z;
+                let (x, y) = (o.x + tm * d.x, o.y + tm * d.y);
+                if x * x + y * y <= self.r * self.r {
+                    times.push(tm);
+                }
+            }
+        }
+        times
+    }
+}
+
+impl CanHit for Cylinder {
+    fn get_hit_time(&self, target_ray: &Ray) -> f64 {
+        get_first_time(&self.get_crossings(target_ray))
+    }
+
+    fn get_hit_intervals(&self, target_ray: &Ray) -> Vec<(f64, f64)> {
+        if !self.capped {
+            return vec![]; // Not closed.
+        }
+        get_intervals(self.get_crossings(target_ray))
+    }
+
+    fn get_hit_normal(&self, pos: Vec3) -> Vec3 {
+        let p = self.frame.to_local(pos - self.centre);
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        // The caps if they are closer than the side.
+        if self.capped && (p.z.min(self.h - p.z)) < (self.r - rho).abs() {
+            let sign = if p.z < self.h / 2.0 { -1.0 } else { 1.0 };
+            return self.frame.to_world(Vec3::make_vec3(0.0, 0.0, sign));
+        }
+        self.frame
+            .to_world(Vec3::make_vec3(p.x, p.y, 0.0))
+            .normalize()
+    }
+
+    fn get_uv(&self, pos: Vec3) -> (f64, f64) {
+        // v along the axis, the caps take the v of their rim.
+        let p = self.frame.to_local(pos - self.centre);
+        (get_angle_u(p), (p.z / self.h).clamp(0.0, 1.0))
+    }
+
+    fn get_tangent(&self, pos: Vec3) -> Vec3 {
+        get_tangent_around(
+            self.frame.to_world(Vec3::make_vec3(0.0, 0.0, 1.0)),
+            self.get_hit_normal(pos),
+        )
+    }
+
+    fn get_bounding_box(&self) -> Option<Aabb> {
+        let axis = self.frame.to_world(Vec3::make_vec3(0.0, 0.0, 1.0));
+        let bottom = get_disk_box(self.centre, axis, self.r);
+        let top = get_disk_box(self.centre + self.h * axis, axis, self.r);
+        Some(bottom.surround(&top))
+    }
+}
+
+//--------------------------------    Struct Cone    -------------------------------------
+
+pub struct Cone {
+    centre: Vec3, // Of the base, the apex is h along the axis.
+    frame: Frame,
+    r: f64,
+    h: f64,
+    capped: bool, // Closed by the base.
+    pub material: Mat,
+}
+
+impl Cone {
+    pub fn make_cone(
+        centre: Vec3,
+        axis: Vec3,
+        r: f64,
+        h: f64,
+        capped: bool,
+        material: Mat,
+    ) -> Cone {
+        Cone {
+            centre,
+            frame: Frame::make_frame(axis),
+            r,
+            h,
+            capped,
+            material,
+        }
+    }
+
+    fn get_crossings(&self, target_ray: &Ray) -> Vec<f64> {
+        // The side is x^2 + y^2 = (r - k z)^2, with k the slope of the radius.
+        let o = self.frame.to_local(target_ray.get_pos() - self.centre);
+        let d = self.frame.to_local(target_ray.get_dir());
+        let k = self.r / self.h;
+        let q = self.r - k * o.z;
+        let mut times = Vec::new();
+        for tm in solve_quadratic(
+            d.x * d.x + d.y * d.y - k * k * d.z * d.z,
+            2.0 * (o.x * d.x + o.y * d.y + q * k * d.z),
+            o.x * o.x + o.y * o.y - q * q,
+        ) {
+            let z = o.z + tm * d.z;
+            if z >= 0.0 && z <= self.h {
+                times.push(tm);
+            }
+        }
+        if self.capped && d.z.abs() > EPS {
+            let tm = -o.z / d.z;
+            let (x, y) = (o.x + tm * d.x, o.y + tm * d.y);
+            if x * x + y * y <= self.r * self.r {
+                times.push(tm);
+            }
+        }
+        times
+    }
+}
+
+impl CanHit for Cone {
+    fn get_hit_time(&self, target_ray: &Ray) -> f64 {
+        get_first_time(&self.get_crossings(target_ray))
+    }
+
+    fn get_hit_intervals(&self, target_ray: &Ray) -> Vec<(f64, f64)> {
+        if !self.capped {
+            return vec![]; // Not closed.
+        }
+        get_intervals(self.get_crossings(target_ray))
+    }
+
+    fn get_hit_normal(&self, pos: Vec3) -> Vec3 {
+        let p = self.frame.to_local(pos - self.centre);
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        let k = self.r / self.h;
+        let slant = (1.0 + k * k).sqrt();
+        // The base if it is closer than the side.
+        if self.capped && p.z < (rho - (self.r - k * p.z)).abs() / slant {
+            return self.frame.to_world(Vec3::make_vec3(0.0, 0.0, -1.0));
+        }
+        if rho < EPS {
+            return self.frame.to_world(Vec3::make_vec3(0.0, 0.0, 1.0)); // The apex.
+        }
+        self.frame
+            .to_world(Vec3::make_vec3(p.x, p.y, k * rho))
+            .normalize()
+    }
+
+    fn get_uv(&self, pos: Vec3) -> (f64, f64) {
+        let p = self.frame.to_local(pos - self.centre);
+        (get_angle_u(p), (p.z / self.h).clamp(0.0, 1.0))
+    }
+
+    fn get_tangent(&self, pos: Vec3) -> Vec3 {
+        get_tangent_around(
+            self.frame.to_world(Vec3::make_vec3(0.0, 0.0, 1.0)),
+            self.get_hit_normal(pos),
+        )
+    }
+
+    fn get_bounding_box(&self) -> Option<Aabb> {
+        let axis = self.frame.to_world(Vec3::make_vec3(0.0, 0.0, 1.0));
+        let apex = self.centre + self.h * axis;
+        Some(get_disk_box(self.centre, axis, self.r).surround(&Aabb::make_aabb(apex, apex)))
+    }
+}
+
+//-------------------------------    Struct Torus    -------------------------------------
+
+pub struct Torus {
+    centre: Vec3,
+    frame: Frame,
+    major: f64, // From the centre to the middle of the tube.
+    minor: f64, // Of the tube.
+    pub material: Mat,
+}
+
+impl Torus {
+    pub fn make_torus(centre: Vec3, axis: Vec3, major: f64, minor: f64, material: Mat) -> Torus {
+        Torus {
+            centre,
+            frame: Frame::make_frame(axis),
+            major,
+            minor,
+            material,
+        }
+    }
+
+    fn get_crossings(&self, target_ray: &Ray) -> Vec<f64> {
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2), quartic in the time.
+        let mut o = self.frame.to_local(target_ray.get_pos() - self.centre);
+        let d = self.frame.to_local(target_ray.get_dir());
+
+        // Starts from the bounding sphere, for the precision of the quartic.
+        let bound = self.major + self.minor;
+        let mut start = 0.0;
+        if dot(o, o) > bound * bound {
+            let roots = solve_quadratic(1.0, 2.0 * dot(o, d), dot(o, o) - bound * bound);
+            match roots.first() {
+                Some(tm) if *tm > 0.0 => start = *tm,
+                _ => return vec![],
+            }
+            o = o + start * d;
+        }
+
+        let (r2, minor2) = (self.major * self.major, self.minor * self.minor);
+        let n = dot(o, d);
+        let g = dot(o, o) + r2 - minor2;
+        solve_quartic(
+            4.0 * n,
+            4.0 * n * n + 2.0 * g - 4.0 * r2 * (d.x * d.x + d.y * d.y),
+            4.0 * n * g - 8.0 * r2 * (o.x * d.x + o.y * d.y),
+            g * g - 4.0 * r2 * (o.x * o.x + o.y * o.y),
+        )
+        .into_iter()
+        .map(|tm| tm + start)
+        .collect()
+    }
+}
+
+impl CanHit for Torus {
+    fn get_hit_time(&self, target_ray: &Ray) -> f64 {
+        get_first_time(&self.get_crossings(target_ray))
+    }
+
+    fn get_hit_intervals(&self, target_ray: &Ray) -> Vec<(f64, f64)> {
+        get_intervals(self.get_crossings(target_ray))
+    }
+
+    fn get_hit_normal(&self, pos: Vec3) -> Vec3 {
+        // From the middle of the tube.
+        let p = self.frame.to_local(pos - self.centre);
+        let rho = (p.x * p.x + p.y * p.y).sqrt().max(EPS);
+        let ring = Vec3::make_vec3(self.major * p.x / rho, self.major * p.y / rho, 0.0);
+        self.frame.to_world(p - ring).normalize()
+    }
+
+    fn get_uv(&self, pos: Vec3) -> (f64, f64) {
+        // u around the axis, v around the tube from its outer side.
+        let p = self.frame.to_local(pos - self.centre);
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        let v = p.z.atan2(rho - self.major) / (2.0 * PI);
+        (get_angle_u(p), v.rem_euclid(1.0))
+    }
+
+    fn get_tangent(&self, pos: Vec3) -> Vec3 {
+        get_tangent_around(
+            self.frame.to_world(Vec3::make_vec3(0.0, 0.0, 1.0)),
+            self.get_hit_normal(pos),
+        )
+    }
+
+    fn get_bounding_box(&self) -> Option<Aabb> {
+        let axis = self.frame.to_world(Vec3::make_vec3(0.0, 0.0, 1.0));
+        let extent = |n: f64| {
+            (self.major + self.minor) * (1.0 - n * n).max(0.0).sqrt() + self.minor * n.abs()
+        };
+        let half = Vec3::make_vec3(extent(axis.x), extent(axis.y), extent(axis.z));
+        Some(Aabb::make_aabb(self.centre - half, self.centre + half))
+    }
+}
+
+//------------------------------    Struct Capsule    ------------------------------------
+
+pub struct Capsule {
+    start: Vec3, // Centres of the two hemispheres.
+    end: Vec3,
+    frame: Frame,
+    h: f64,
+    r: f64,
+    pub material: Mat,
+}
+
+impl Capsule {
+    pub fn make_capsule(start: Vec3, end: Vec3, r: f64, material: Mat) -> Capsule {
+        let axis = end - start;
+        let h = axis.get_len();
+        Capsule {
+            start,
+            end,
+            frame: Frame::make_frame(if h < EPS {
+                Vec3::make_vec3(0.0, 1.0, 0.0)
+            } else {
+                axis
+            }),
+            h,
+            r,
+            material,
+        }
+    }
+
+    fn get_crossings(&self, target_ray: &Ray) -> Vec<f64> {
+        // The open cylinder and the outer halves of the two spheres.
+        let o = self.frame.to_local(target_ray.get_pos() - self.start);
+        let d = self.frame.to_local(target_ray.get_dir());
+        let mut times = Vec::new();
+        for tm in solve_quadratic(
+            d.x * d.x + d.y * d.y,
+            2.0 * (o.x * d.x + o.y * d.y),
+            o.x * o.x + o.y * o.y - self.r * self.r,
+        ) {
+            let z = o.z + tm * d.z;
+            if z >= 0.0 && z <= self.h {
+                times.push(tm);
+            }
+        }
+        for (z0, outside) in [(0.0, -1.0), (self.h, 1.0)].iter() {
+            let oc = o - Vec3::make_vec3(0.0, 0.0, *z0);
+            for tm in solve_quadratic(1.0, 2.0 * dot(oc, d), dot(oc, oc) - self.r * self.r) {
+                if (oc.z + tm * d.z) * outside >= 0.0 {
+                    times.push(tm);
+                }
+            }
+        }
+        times
+    }
+}
+
+impl CanHit for Capsule {
+    fn get_hit_time(&self, target_ray: &Ray) -> f64 {
+        get_first_time(&self.get_crossings(target_ray))
+    }
+
+    fn get_hit_intervals(&self, target_ray: &Ray) -> Vec<(f64, f64)> {
+        get_intervals(self.get_crossings(target_ray))
+    }
+
+    fn get_hit_normal(&self, pos: Vec3) -> Vec3 {
+        // From the closest point of the segment.
+        let p = self.frame.to_local(pos - self.start);
+        let on_axis = Vec3::make_vec3(0.0, 0.0, p.z.clamp(0.0, self.h));
+        self.frame.to_world(p - on_axis).normalize()
+    }
+
+    fn get_uv(&self, pos: Vec3) -> (f64, f64) {
+        // v along the whole length, from the tip of the start.
+        let p = self.frame.to_local(pos - self.start);
+        let v = (p.z + self.r) / (self.h + 2.0 * self.r);
+        (get_angle_u(p), v.clamp(0.0, 1.0))
+    }
+
+    fn get_tangent(&self, pos: Vec3) -> Vec3 {
+        get_tangent_around(
+            self.frame.to_world(Vec3::make_vec3(0.0, 0.0, 1.0)),
+            self.get_hit_normal(pos),
+        )
+    }
+
+    fn get_bounding_box(&self) -> Option<Aabb> {
+        let half = Vec3::make_vec3(self.r, self.r, self.r);
+        let a = Aabb::make_aabb(self.start - half, self.start + half);
+        let b = Aabb::make_aabb(self.end - half, self.end + half);
+        Some(a.surround(&b))
+    }
+}
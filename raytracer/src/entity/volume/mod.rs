@@ -0,0 +1,196 @@
+//--------------------------------    Mod Volume    --------------------------------------
+// Heterogeneous participating media in a box, whose density comes from a voxel grid.
+// Rays collide with the medium by delta tracking, and shadow rays are attenuated by
+// ratio tracking (Novak et al. 2014), both against the maximum density of the grid.
+use crate::entity::aabb::Aabb;
+use crate::entity::material::*;
+use crate::entity::CanHit;
+use crate::graphics::ray::Ray;
+use crate::math_support::*;
+use std::fs::File;
+use std::io::Read;
+
+const MAGIC: &[u8; 4] = b"RTVG";
+
+//---------------------------------    Struct Grid    ------------------------------------
+
+pub struct Grid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    data: Vec<f64>, // x first, then y, then z.
+    max: f64,
+}
+
+impl Grid {
+    pub fn make_grid(nx: usize, ny: usize, nz: usize, data: Vec<f64>) -> Grid {
+        let max = data.iter().cloned().fold(0.0, f64::max);
+        Grid {
+            nx,
+            ny,
+            nz,
+            data,
+            max,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Grid, String> {
+        // Little-endian: magic, nx, ny, nz as u32, then nx * ny * nz f32 densities.
+        let mut data = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|err| err.to_string())?;
+        if data.len() < 16 || &data[0..4] != MAGIC {
+            return Err(format!("{} is not a voxel grid", path));
+        }
+        let read_u32 = |at: usize| {
+            u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]) as usize
+        };
+        let (nx, ny, nz) = (read_u32(4), read_u32(8), read_u32(12));
+        let size = nx.checked_mul(ny).and_then(|size| size.checked_mul(nz));
+        let len = size
+            .and_then(|size| size.checked_mul(4))
+            .and_then(|len| len.checked_add(16));
+        let size = match (size, len) {
+            (Some(size), Some(len)) if size > 0 && len <= data.len() => size,
+            _ => return Err(format!("{} is truncated", path)),
+        };
+        let densities = (0..size)
+            .map(|id| {
+                let at = 16 + id * 4;
+                let b = [data[at], data[at + 1], data[at + 2], data[at + 3]];
+                (f32::from_le_bytes(b) as f64).max(0.0)
+            })
+            .collect();
+        Ok(Grid::make_grid(nx, ny, nz, densities))
+    }
+
+    pub fn make_cloud(n: usize) -> Grid {
+        // Procedural puff: a ball eroded by fractal value noise.
+        let mut data = Vec::with_capacity(n * n * n);
+        for k in 0..n {
+            for j in 0..n {
+                for i in 0..n {
+                    let p = Vec3::make_vec3(
+                        (i as f64 + 0.5) / n as f64,
+                        (j as f64 + 0.5) / n as f64,
+                        (k as f64 + 0.5) / n as f64,
+                    );
+                    let r = (2.0 * (p - Vec3::make_vec3(0.5, 0.5, 0.5))).get_len();
+                    let density = 1.0 - r + 0.8 * (get_fbm(4.0 * p) - 0.5);
+                    data.push((3.0 * density).clamp(0.0, 1.0));
+                }
+            }
+        }
+        Grid::make_grid(n, n, n, data)
+    }
+
+    fn get_voxel(&self, i: usize, j: usize, k: usize) -> f64 {
+        self.data[(k * self.ny + j) * self.nx + i]
+    }
+
+    pub fn get_density(&self, p: Vec3) -> f64 {
+        // Trilinear, p in the unit cube.
+        let axis = |val: f64, n: usize| {
+            let t = (val * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            let i = (t as usize).min(n.saturating_sub(2));
+            (i, (i + 1).min(n - 1), t - i as f64)
+        };
+        let (i0, i1, fx) = axis(p.x, self.nx);
+        let (j0, j1, fy) = axis(p.y, self.ny);
+        let (k0, k1, fz) = axis(p.z, self.nz);
+        let lerp = |a: f64, b: f64, t: f64| a * (1.0 - t) + b * t;
+        let face = |k: usize| {
+            lerp(
+                lerp(self.get_voxel(i0, j0, k), self.get_voxel(i1, j0, k), fx),
+                lerp(self.get_voxel(i0, j1, k), self.get_voxel(i1, j1, k), fx),
+                fy,
+            )
+        };
+        lerp(face(k0), face(k1), fz)
+    }
+}
+
+//--------------------------------    Struct Volume    -----------------------------------
+
+pub struct Volume {
+    bbox: Aabb,
+    grid: Grid,
+    density: f64, // Extinction of grid value 1.
+    pub material: Mat,
+}
+
+impl Volume {
+    pub fn make_volume(bbox: Aabb, grid: Grid, density: f64, material: Mat) -> Volume {
+        // The material scatters at the collisions, usually Mat::Med.
+        Volume {
+            bbox,
+            grid,
+            density,
+            material,
+        }
+    }
+
+    fn get_sigma_t(&self, pos: Vec3) -> f64 {
+        let d = self.bbox.get_diagonal();
+        let p = pos - self.bbox.min;
+        self.density
+            * self
+                .grid
+                .get_density(Vec3::make_vec3(p.x / d.x, p.y / d.y, p.z / d.z))
+    }
+
+    fn get_sigma_max(&self) -> f64 {
+        self.density * self.grid.max
+    }
+}
+
+impl CanHit for Volume {
+    fn get_hit_time(&self, target_ray: &Ray) -> f64 {
+        // Delta tracking, a real collision or -1 if the ray passes through.
+        let sigma_max = self.get_sigma_max();
+        let (t0, t1) = match self.bbox.get_hit_range(target_ray) {
+            Some(range) if sigma_max > 0.0 => range,
+            _ => return -1.0,
+        };
+        let mut tm = t0.max(0.0);
+        loop {
+            tm -= (1.0 - rand_0_1()).ln() / sigma_max;
+            if tm >= t1 {
+                return -1.0;
+            }
+            let pos = target_ray.get_pos() + tm * target_ray.get_dir();
+            if rand_0_1() * sigma_max < self.get_sigma_t(pos) {
+                return tm;
+            }
+        }
+    }
+
+    fn get_hit_normal(&self, pos: Vec3) -> Vec3 {
+        Vec3::make_vec3(0.0, 0.0, 0.0) // Media have no surface.
+    }
+
+    fn get_bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn get_transmittance(&self, target_ray: &Ray, dist: f64) -> f64 {
+        // Ratio tracking.
+        let sigma_max = self.get_sigma_max();
+        let (t0, t1) = match self.bbox.get_hit_range(target_ray) {
+            Some(range) if sigma_max > 0.0 => range,
+            _ => return 1.0,
+        };
+        let t1 = t1.min(dist);
+        let mut tm = t0.max(0.0);
+        let mut res = 1.0;
+        loop {
+            tm -= (1.0 - rand_0_1()).ln() / sigma_max;
+            if tm >= t1 {
+                return res;
+            }
+            let pos = target_ray.get_pos() + tm * target_ray.get_dir();
+            res *= 1.0 - self.get_sigma_t(pos) / sigma_max;
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//-------------------------------    Denoiser    -----------------------------------------
+// Edge-avoiding a-trous wavelet filter (Dammertz et al. 2010),
+// guided by the first-hit albedo, normal and depth of each pixel.
+use crate::math_support::*;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+const ITERATIONS: i32 = 5; // Filter footprint is 4 * 2^ITERATIONS + 1 pixels.
+const SIGMA_COLOR: f64 = 0.6; // Halved on every iteration.
+const SIGMA_NORMAL: f64 = 0.3;
+const SIGMA_ALBEDO: f64 = 0.1;
+const SIGMA_DEPTH: f64 = 0.05; // Relative to the depth of the centre pixel.
+const EPS_ALBEDO: f64 = 0.001; // Keeps black albedo from dividing by zero.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+const MAGIC: &[u8; 4] = b"RTFB";
+
+//-----------------------------    Struct FeatureBuffers    ------------------------------
+// All buffers are linear and stored row by row, from the bottom row of the image.
+pub struct FeatureBuffers {
+    pub width: u32,
+    pub height: u32,
+    pub color: Vec<Vec3>,
+    pub albedo: Vec<Vec3>,
+    pub normal: Vec<Vec3>,
+    pub depth: Vec<f64>,
+}
+
+impl FeatureBuffers {
+    pub fn make_buffers(width: u32, height: u32) -> FeatureBuffers {
+        let size = width as usize * height as usize;
+        FeatureBuffers {
+            width,
+            height,
+            color: vec![Vec3::make_vec3(0.0, 0.0, 0.0); size],
+            albedo: vec![Vec3::make_vec3(0.0, 0.0, 0.0); size],
+            normal: vec![Vec3::make_vec3(0.0, 0.0, 0.0); size],
+            depth: vec![0.0; size],
+        }
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        // Little-endian: magic, width, height, then 10 f32 per pixel.
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&self.width.to_le_bytes())?;
+        file.write_all(&self.height.to_le_bytes())?;
+        for id in 0..self.depth.len() {
+            for val in &[self.color[id], self.albedo[id], self.normal[id]] {
+                for c in &[val.x, val.y, val.z] {
+                    file.write_all(&(*c as f32).to_le_bytes())?;
+                }
+            }
+            file.write_all(&(self.depth[id] as f32).to_le_bytes())?;
+        }
+        file.flush()
+    }
+
+    pub fn load(path: &str) -> io::Result<FeatureBuffers> {
+        let mut data = Vec::new();
+        BufReader::new(File::open(path)?).read_to_end(&mut data)?;
+        if data.len() < 12 || &data[0..4] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a feature buffer file",
+            ));
+        }
+        let read_u32 =
+            |at: usize| u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+        let read_f64 = |at: usize| {
+            f32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]) as f64
+        };
+        let read_vec3 =
+            |at: usize| Vec3::make_vec3(read_f64(at), read_f64(at + 4), read_f64(at + 8));
+
+        // Checked before allocating, the header may be corrupt.
+        let (width, height) = (read_u32(4), read_u32(8));
+        let expected = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|size| size.checked_mul(40))
+            .and_then(|len| len.checked_add(12));
+        if expected != Some(data.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "feature buffer file is truncated",
+            ));
+        }
+        let mut res = FeatureBuffers::make_buffers(width, height);
+        for id in 0..res.depth.len() {
+            let at = 12 + id * 40;
+            res.color[id] = read_vec3(at);
+            res.albedo[id] = read_vec3(at + 12);
+            res.normal[id] = read_vec3(at + 24);
+            res.depth[id] = read_f64(at + 36);
+        }
+        Ok(res)
+    }
+
+    pub fn denoise(&self) -> Vec<Vec3> {
+        // Filter the irradiance (color divided by albedo) so textures are kept sharp.
+        let mut irradiance: Vec<Vec3> = (0..self.color.len())
+            .map(|id| demodulate(self.color[id], self.albedo[id]))
+            .collect();
+
+        let mut sigma_color = SIGMA_COLOR;
+        for i in 0..ITERATIONS {
+            irradiance = self.do_atrous_pass(&irradiance, 1 << i, sigma_color);
+            sigma_color /= 2.0;
+        }
+
+        (0..irradiance.len())
+            .map(|id| irradiance[id] * self.albedo[id].max_with(EPS_ALBEDO))
+            .collect()
+    }
+
+    fn do_atrous_pass(&self, input: &[Vec3], step: i32, sigma_color: f64) -> Vec<Vec3> {
+        let (w, h) = (self.width as i32, self.height as i32);
+        let mut output = vec![Vec3::make_vec3(0.0, 0.0, 0.0); input.len()];
+
+        for y in 0..h {
+            for x in 0..w {
+                let p = (y * w + x) as usize;
+                let mut sum = Vec3::make_vec3(0.0, 0.0, 0.0);
+                let mut weight_sum: f64 = 0.0;
+
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let qx = x + (i as i32 - 2) * step;
+                        let qy = y + (j as i32 - 2) * step;
+                        if qx < 0 || qy < 0 || qx >= w || qy >= h {
+                            continue;
+                        }
+                        let q = (qy * w + qx) as usize;
+
+                        let dc = input[p] - input[q];
+                        let dn = self.normal[p] - self.normal[q];
+                        let da = self.albedo[p] - self.albedo[q];
+                        let dz = (self.depth[p] - self.depth[q]).abs()
+                            / (SIGMA_DEPTH * self.depth[p]).max(EPS);
+
+                        let weight = kx
+                            * ky
+                            * (-dot(dc, dc) / (sigma_color * sigma_color)).exp()
+                            * (-dot(dn, dn) / (SIGMA_NORMAL * SIGMA_NORMAL)).exp()
+                            * (-dot(da, da) / (SIGMA_ALBEDO * SIGMA_ALBEDO)).exp()
+                            * (-dz).exp();
+                        sum = sum + weight * input[q];
+                        weight_sum += weight;
+                    }
+                }
+                // The centre pixel always has a positive weight.
+                output[p] = sum / weight_sum;
+            }
+        }
+        output
+    }
+}
+
+fn demodulate(color: Vec3, albedo: Vec3) -> Vec3 {
+    let albedo = albedo.max_with(EPS_ALBEDO);
+    Vec3::make_vec3(color.x / albedo.x, color.y / albedo.y, color.z / albedo.z)
+}
@@ -0,0 +1,88 @@
+//----------------------------------    Struct Film    -----------------------------------
+// Samples are splatted into every pixel within the filter radius,
+// and each pixel keeps the weighted sum of the samples it received.
+use crate::graphics::denoiser::FeatureBuffers;
+use crate::graphics::filter::Filter;
+use crate::math_support::*;
+
+pub struct Film {
+    width: u32,
+    height: u32,
+    color_sum: Vec<Vec3>,
+    weight_sum: Vec<f64>,
+    // Features are box filtered inside the pixel.
+    albedo_sum: Vec<Vec3>,
+    normal_sum: Vec<Vec3>,
+    depth_sum: Vec<f64>,
+    feature_count: Vec<i32>,
+}
+
+impl Film {
+    pub fn make_film(width: u32, height: u32) -> Film {
+        let size = (width * height) as usize;
+        Film {
+            width,
+            height,
+            color_sum: vec![Vec3::make_vec3(0.0, 0.0, 0.0); size],
+            weight_sum: vec![0.0; size],
+            albedo_sum: vec![Vec3::make_vec3(0.0, 0.0, 0.0); size],
+            normal_sum: vec![Vec3::make_vec3(0.0, 0.0, 0.0); size],
+            depth_sum: vec![0.0; size],
+            feature_count: vec![0; size],
+        }
+    }
+
+    pub fn add_sample(&mut self, px: f64, py: f64, color: Vec3, filter: &Filter) {
+        // (px, py) is the sample position in pixels on the film.
+        let radius = filter.get_radius();
+        let x0 = (px - 0.5 - radius).ceil().max(0.0) as u32;
+        let y0 = (py - 0.5 - radius).ceil().max(0.0) as u32;
+        let x1 = ((px - 0.5 + radius).floor() as i64).min(self.width as i64 - 1);
+        let y1 = ((py - 0.5 + radius).floor() as i64).min(self.height as i64 - 1);
+
+        for y in y0 as i64..=y1 {
+            for x in x0 as i64..=x1 {
+                let weight = filter.evaluate(x as f64 + 0.5 - px, y as f64 + 0.5 - py);
+                if weight == 0.0 {
+                    continue;
+                }
+                let id = (y as u32 * self.width + x as u32) as usize;
+                self.color_sum[id] = self.color_sum[id] + weight * color;
+                self.weight_sum[id] += weight;
+            }
+        }
+    }
+
+    pub fn add_features(&mut self, x: u32, y: u32, albedo: Vec3, normal: Vec3, depth: f64) {
+        let id = (y * self.width + x) as usize;
+        self.albedo_sum[id] = self.albedo_sum[id] + albedo;
+        self.normal_sum[id] = self.normal_sum[id] + normal;
+        self.depth_sum[id] += depth;
+        self.feature_count[id] += 1;
+    }
+
+    pub fn get_color(&self, x: u32, y: u32) -> Vec3 {
+        // Linear color. Negative lobes of some filters may ring below zero, so clamp it.
+        let id = (y * self.width + x) as usize;
+        if self.weight_sum[id] < EPS {
+            return Vec3::make_vec3(0.0, 0.0, 0.0);
+        }
+        let color = self.color_sum[id] / self.weight_sum[id];
+        Vec3::make_vec3(color.x.max(0.0), color.y.max(0.0), color.z.max(0.0))
+    }
+
+    pub fn get_buffers(&self) -> FeatureBuffers {
+        let mut res = FeatureBuffers::make_buffers(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let id = (y * self.width + x) as usize;
+                let n = self.feature_count[id].max(1) as f64;
+                res.color[id] = self.get_color(x, y);
+                res.albedo[id] = self.albedo_sum[id] / n;
+                res.normal[id] = self.normal_sum[id] / n;
+                res.depth[id] = self.depth_sum[id] / n;
+            }
+        }
+        res
+    }
+}
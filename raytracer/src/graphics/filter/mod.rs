@@ -0,0 +1,89 @@
+//------------------------------    Reconstruction Filters    ----------------------------
+// All filters are separable, evaluated with offsets in pixels from the pixel centre.
+use std::f64::consts::PI;
+
+pub enum Filter {
+    Box(f64),                // Radius.
+    Tent(f64),               // Radius.
+    Gaussian(f64, f64),      // Radius, falloff alpha.
+    Mitchell(f64, f64, f64), // Radius, B, C.
+    Lanczos(f64),            // Radius, which is also the number of lobes.
+}
+
+impl Filter {
+    pub fn make_box(radius: f64) -> Filter {
+        Filter::Box(radius)
+    }
+
+    pub fn make_tent(radius: f64) -> Filter {
+        Filter::Tent(radius)
+    }
+
+    pub fn make_gaussian(radius: f64, alpha: f64) -> Filter {
+        Filter::Gaussian(radius, alpha)
+    }
+
+    pub fn make_mitchell(radius: f64, b: f64, c: f64) -> Filter {
+        // B = C = 1/3 is the recommended one.
+        Filter::Mitchell(radius, b, c)
+    }
+
+    pub fn make_lanczos(radius: f64) -> Filter {
+        Filter::Lanczos(radius)
+    }
+
+    pub fn get_radius(&self) -> f64 {
+        match self {
+            Filter::Box(r) => *r,
+            Filter::Tent(r) => *r,
+            Filter::Gaussian(r, _) => *r,
+            Filter::Mitchell(r, _, _) => *r,
+            Filter::Lanczos(r) => *r,
+        }
+    }
+
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x > self.get_radius() {
+            return 0.0;
+        }
+        match self {
+            Filter::Box(r) => 1.0,
+            Filter::Tent(r) => r - x,
+            Filter::Gaussian(r, alpha) => {
+                // Shifted down so that it reaches zero at the radius.
+                ((-alpha * x * x).exp() - (-alpha * r * r).exp()).max(0.0)
+            }
+            Filter::Mitchell(r, b, c) => Filter::mitchell_1d(2.0 * x / r, *b, *c),
+            Filter::Lanczos(r) => Filter::sinc(x) * Filter::sinc(x / r),
+        }
+    }
+
+    fn mitchell_1d(x: f64, b: f64, c: f64) -> f64 {
+        // x is in [0, 2].
+        if x > 1.0 {
+            ((-b - 6.0 * c) * x * x * x
+                + (6.0 * b + 30.0 * c) * x * x
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c))
+                / 6.0
+        } else {
+            ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                + (6.0 - 2.0 * b))
+                / 6.0
+        }
+    }
+
+    fn sinc(x: f64) -> f64 {
+        if x < 1e-5 {
+            1.0
+        } else {
+            (PI * x).sin() / (PI * x)
+        }
+    }
+}
@@ -1,15 +1,23 @@
+pub mod denoiser;
+pub mod film;
+pub mod filter;
 pub mod ray;
+pub mod sampler;
+pub mod spectrum;
 
 use crate::math_support::*;
 use crate::*;
+use film::Film;
 use ray::Ray;
+use sampler::PixelStat;
+use spectrum::spectrum_to_rgb;
 
-pub fn render_pixel(x: u32, y: u32) -> Vec3 {
-    let mut samples = SAMPLES_PER_PIXEL;
-    let mut color: Vec3 = Vec3::make_vec3(0.0, 0.0, 0.0);
+pub fn render_pixel(x: u32, y: u32, film: &mut Film) -> i32 {
+    // Splats the samples into the film, returns the number of samples taken.
+    let mut stat = PixelStat::make_stat();
 
-    while samples > 0 {
-        // Anti aliasing with SAMPLES_PER_PIXEL samples.
+    while !stat.is_converged() {
+        // Anti aliasing with adaptive number of samples.
 
         let dlt_x = rand_0_1();
         let dlt_y = rand_0_1();
@@ -20,12 +28,17 @@ pub fn render_pixel(x: u32, y: u32) -> Vec3 {
         let target_ray: Ray =
             Ray::make_ray(origin, *lower_left_corner + hor * u + ver * v - origin);
 
-        color = color + wld.trace_ray_color(&target_ray);
-
-        samples -= 1;
+        // Features of the same first hit as the color, which a cutout may make random.
+        let (color, (albedo, normal, depth)) = if SPECTRAL_MODE {
+            let (values, lambdas, features) = wld.trace_ray_spectrum(&target_ray);
+            (spectrum_to_rgb(values, lambdas), features)
+        } else {
+            wld.trace_ray_color(&target_ray)
+        };
+        stat.add_sample(color);
+        film.add_sample(x as f64 + dlt_x, y as f64 + dlt_y, color, &flt);
+        film.add_features(x, y, albedo, normal, depth);
     }
 
-    color = color / (SAMPLES_PER_PIXEL as f64);
-    color = color.sqrt_for_gamma_correction(); // Gamma correction.
-    color
+    stat.get_count()
 }
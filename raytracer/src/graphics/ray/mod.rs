@@ -7,6 +7,7 @@ pub use math_support::Vec3;
 pub struct Ray {
     pub pos: Vec3,
     pub dir: Vec3,
+    pub wavelength: f64, // In nm, 0 for rgb.
 }
 
 impl Ray {
@@ -14,6 +15,15 @@ impl Ray {
         Ray {
             pos,
             dir: dir.normalize(),
+            wavelength: 0.0,
+        }
+    }
+    pub fn make_ray_at(pos: Vec3, dir: Vec3, wavelength: f64) -> Ray {
+        // Carries a single wavelength.
+        Ray {
+            pos,
+            dir: dir.normalize(),
+            wavelength,
         }
     }
     pub fn get_pos(&self) -> Vec3 {
@@ -22,4 +32,7 @@ impl Ray {
     pub fn get_dir(&self) -> Vec3 {
         self.dir
     }
+    pub fn get_wavelength(&self) -> f64 {
+        self.wavelength
+    }
 }
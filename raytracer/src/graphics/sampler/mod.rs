@@ -0,0 +1,77 @@
+//--------------------------------    Adaptive Sampler    --------------------------------
+// Running statistics of the samples of a single pixel.
+use crate::math_support::*;
+use crate::{MAX_SAMPLES_PER_PIXEL, MIN_SAMPLES_PER_PIXEL, NOISE_THRESHOLD};
+
+const DARK_LUMINANCE: f64 = 0.01; // Floor of the mean, so black pixels can converge.
+
+pub struct PixelStat {
+    count: i32,
+    mean: Vec3,
+    lum_mean: f64,
+    lum_m2: f64, // Sum of squared differences from the luminance mean.
+}
+
+impl PixelStat {
+    pub fn make_stat() -> PixelStat {
+        PixelStat {
+            count: 0,
+            mean: Vec3::make_vec3(0.0, 0.0, 0.0),
+            lum_mean: 0.0,
+            lum_m2: 0.0,
+        }
+    }
+
+    pub fn add_sample(&mut self, color: Vec3) {
+        // Welford's online algorithm.
+        self.count += 1;
+        let n = self.count as f64;
+        self.mean = self.mean + (color - self.mean) / n;
+
+        let lum = luminance(color);
+        let dlt = lum - self.lum_mean;
+        self.lum_mean += dlt / n;
+        self.lum_m2 += dlt * (lum - self.lum_mean);
+    }
+
+    pub fn get_count(&self) -> i32 {
+        self.count
+    }
+
+    pub fn get_mean(&self) -> Vec3 {
+        self.mean
+    }
+
+    pub fn get_variance(&self) -> f64 {
+        // Sample variance of the luminance.
+        if self.count < 2 {
+            0.0
+        } else {
+            self.lum_m2 / (self.count - 1) as f64
+        }
+    }
+
+    pub fn is_converged(&self) -> bool {
+        if self.count < MIN_SAMPLES_PER_PIXEL {
+            return false;
+        }
+        if self.count >= MAX_SAMPLES_PER_PIXEL {
+            return true;
+        }
+        // Stop once the standard error of the mean is small relative to the mean.
+        let std_err = (self.get_variance() / self.count as f64).sqrt();
+        std_err <= NOISE_THRESHOLD * self.lum_mean.max(DARK_LUMINANCE)
+    }
+}
+
+//--------------------------------    Heatmap    -----------------------------------------
+pub fn heat_color(count: i32) -> Vec3 {
+    // Blue for the min budget, through green, to red for the max budget.
+    let span = (MAX_SAMPLES_PER_PIXEL - MIN_SAMPLES_PER_PIXEL).max(1) as f64;
+    let t = ((count - MIN_SAMPLES_PER_PIXEL) as f64 / span).clamp(0.0, 1.0);
+    if t < 0.5 {
+        Vec3::make_vec3(0.0, t * 2.0, 1.0 - t * 2.0)
+    } else {
+        Vec3::make_vec3(t * 2.0 - 1.0, 2.0 - t * 2.0, 0.0)
+    }
+}
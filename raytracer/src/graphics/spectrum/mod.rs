@@ -0,0 +1,223 @@
+// Mod Spectrum: wavelengths in nm and their colour, for paths traced at a single wavelength
+// and for the spectral mode, whose radiance is carried at 3 wavelengths in a Vec3.
+use crate::math_support::*;
+
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 780.0;
+
+// Smits 1999 spectra, sampled evenly from 380 to 720 nm, of which rgb colours are mixed.
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+const SMITS_LAMBDA_MAX: f64 = 720.0;
+
+lazy_static::lazy_static! {
+    // Integral of the rgb response over the visible range, which keeps white light white.
+    static ref response_integral: Vec3 = integrate(get_rgb_response);
+
+    // Integrals of the matching functions, and the rgb of the flat spectrum.
+    static ref cie_integral: Vec3 = integrate(get_cie_xyz);
+    static ref white_rgb: Vec3 = xyz_to_rgb(*cie_integral / cie_integral.y);
+
+    // Rows of the inverse of the rgb of the Smits red, green and blue spectra.
+    static ref illuminant_inverse: [Vec3; 3] = get_illuminant_inverse();
+}
+
+fn integrate<F: Fn(f64) -> Vec3>(func: F) -> Vec3 {
+    // Over the visible range, at every nm.
+    let mut sum = Vec3::make_vec3(0.0, 0.0, 0.0);
+    let mut lambda = LAMBDA_MIN + 0.5;
+    while lambda < LAMBDA_MAX {
+        sum = sum + func(lambda);
+        lambda += 1.0;
+    }
+    sum
+}
+
+pub fn get_cie_xyz(lambda: f64) -> Vec3 {
+    // CIE 1931 colour matching functions, multi-lobe fit of Wyman et al. 2013.
+    let g = |mu: f64, sigma_1: f64, sigma_2: f64| {
+        let t = (lambda - mu) / if lambda < mu { sigma_1 } else { sigma_2 };
+        (-0.5 * t * t).exp()
+    };
+    Vec3::make_vec3(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+fn get_rgb_response(lambda: f64) -> Vec3 {
+    // Negative lobes are cut, so a single wavelength never gives a negative colour.
+    xyz_to_rgb(get_cie_xyz(lambda)).max_with(0.0)
+}
+
+pub fn sample_wavelength() -> (f64, f64) {
+    // Uniform over the visible range, with its pdf.
+    (
+        LAMBDA_MIN + rand_0_1() * (LAMBDA_MAX - LAMBDA_MIN),
+        1.0 / (LAMBDA_MAX - LAMBDA_MIN),
+    )
+}
+
+pub fn get_wavelength_weight(lambda: f64, pdf: f64) -> Vec3 {
+    // Throughput of a path that keeps only lambda, so that the average over lambda is white.
+    let res = get_rgb_response(lambda) / pdf;
+    Vec3::make_vec3(
+        res.x / response_integral.x,
+        res.y / response_integral.y,
+        res.z / response_integral.z,
+    )
+}
+
+//-----------------------------    Spectral Mode    --------------------------------------
+
+pub fn sample_hero_wavelengths() -> Vec3 {
+    // A uniform hero wavelength and two more rotated by a third of the range,
+    // each of them uniform, whose pdf is that of sample_wavelength.
+    let range = LAMBDA_MAX - LAMBDA_MIN;
+    let hero = rand_0_1() * range;
+    let rotate = |i: f64| LAMBDA_MIN + (hero + i * range / 3.0) % range;
+    Vec3::make_vec3(rotate(0.0), rotate(1.0), rotate(2.0))
+}
+
+fn get_smits(table: &[f64; 10], lambda: f64) -> f64 {
+    let t = ((lambda - LAMBDA_MIN) / (SMITS_LAMBDA_MAX - LAMBDA_MIN) * 9.0).clamp(0.0, 9.0);
+    let i = (t as usize).min(8);
+    let f = t - i as f64;
+    table[i] * (1.0 - f) + table[i + 1] * f
+}
+
+pub fn rgb_to_spectrum(rgb: Vec3, lambdas: Vec3) -> Vec3 {
+    // Values of the smooth spectrum of rgb at the 3 wavelengths, rgb itself if lambdas are 0.
+    if lambdas.x <= 0.0 {
+        return rgb;
+    }
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    let at = |lambda: f64| {
+        let s = |table: &[f64; 10]| get_smits(table, lambda);
+        let res = if r <= g && r <= b {
+            r * s(&SMITS_WHITE)
+                + if g <= b {
+                    (g - r) * s(&SMITS_CYAN) + (b - g) * s(&SMITS_BLUE)
+                } else {
+                    (b - r) * s(&SMITS_CYAN) + (g - b) * s(&SMITS_GREEN)
+                }
+        } else if g <= r && g <= b {
+            g * s(&SMITS_WHITE)
+                + if r <= b {
+                    (r - g) * s(&SMITS_MAGENTA) + (b - r) * s(&SMITS_BLUE)
+                } else {
+                    (b - g) * s(&SMITS_MAGENTA) + (r - b) * s(&SMITS_RED)
+                }
+        } else {
+            b * s(&SMITS_WHITE)
+                + if r <= g {
+                    (r - b) * s(&SMITS_YELLOW) + (g - r) * s(&SMITS_GREEN)
+                } else {
+                    (g - b) * s(&SMITS_YELLOW) + (r - g) * s(&SMITS_RED)
+                }
+        };
+        res.max(0.0)
+    };
+    Vec3::make_vec3(at(lambdas.x), at(lambdas.y), at(lambdas.z))
+}
+
+fn get_illuminant_inverse() -> [Vec3; 3] {
+    let get_rgb = |table: &[f64; 10]| {
+        xyz_to_balanced_rgb(integrate(|lambda| {
+            get_smits(table, lambda) * get_cie_xyz(lambda)
+        }))
+    };
+    let (r, g, b) = (
+        get_rgb(&SMITS_RED),
+        get_rgb(&SMITS_GREEN),
+        get_rgb(&SMITS_BLUE),
+    );
+    let det = dot(r, cross(g, b));
+    [cross(g, b) / det, cross(b, r) / det, cross(r, g) / det]
+}
+
+pub fn illuminant_to_spectrum(rgb: Vec3, lambdas: Vec3) -> Vec3 {
+    // For emitted light, rgb itself if lambdas are 0. A mix of the Smits red, green and
+    // blue spectra whose colour is rgb exactly, not kept below 1 as reflectances are.
+    if lambdas.x <= 0.0 {
+        return rgb;
+    }
+    let inverse = *illuminant_inverse;
+    let mix = Vec3::make_vec3(
+        dot(inverse[0], rgb),
+        dot(inverse[1], rgb),
+        dot(inverse[2], rgb),
+    );
+    let at = |lambda: f64| {
+        let basis = Vec3::make_vec3(
+            get_smits(&SMITS_RED, lambda),
+            get_smits(&SMITS_GREEN, lambda),
+            get_smits(&SMITS_BLUE, lambda),
+        );
+        dot(mix, basis).max(0.0)
+    };
+    Vec3::make_vec3(at(lambdas.x), at(lambdas.y), at(lambdas.z))
+}
+
+fn xyz_to_balanced_rgb(xyz: Vec3) -> Vec3 {
+    // In units where the flat spectrum of 1 is rgb white.
+    let rgb = xyz_to_rgb(xyz / cie_integral.y);
+    Vec3::make_vec3(
+        rgb.x / white_rgb.x,
+        rgb.y / white_rgb.y,
+        rgb.z / white_rgb.z,
+    )
+}
+
+pub fn spectrum_to_rgb(values: Vec3, lambdas: Vec3) -> Vec3 {
+    // Integrates the 3 samples against the CIE XYZ matching functions.
+    let pdf = 1.0 / (LAMBDA_MAX - LAMBDA_MIN);
+    let xyz = values.x * get_cie_xyz(lambdas.x)
+        + values.y * get_cie_xyz(lambdas.y)
+        + values.z * get_cie_xyz(lambdas.z);
+    xyz_to_balanced_rgb(xyz / (3.0 * pdf))
+}
+
+//-------------------------------    Blackbody    ----------------------------------------
+
+pub fn get_blackbody(lambda: f64, kelvin: f64) -> f64 {
+    // Planck's law, lambda in nm, in W / (sr m^2 nm).
+    const C: f64 = 299_792_458.0;
+    const H: f64 = 6.626_070_15e-34;
+    const KB: f64 = 1.380_649e-23;
+    let l = lambda * 1e-9;
+    2.0 * H * C * C / (l.powi(5) * ((H * C / (l * KB * kelvin)).exp() - 1.0)) * 1e-9
+}
+
+pub fn get_blackbody_norm(kelvin: f64) -> f64 {
+    // Luminance of the blackbody, dividing by which gives luminance 1.
+    integrate(|lambda| get_blackbody(lambda, kelvin) * get_cie_xyz(lambda)).y / cie_integral.y
+}
+
+pub fn get_blackbody_rgb(kelvin: f64) -> Vec3 {
+    // Colour of luminance 1.
+    let norm = get_blackbody_norm(kelvin);
+    xyz_to_balanced_rgb(integrate(|lambda| {
+        (get_blackbody(lambda, kelvin) / norm) * get_cie_xyz(lambda)
+    }))
+}
@@ -14,6 +14,9 @@ pub mod graphics;
 pub mod math_support;
 pub mod world;
 
+use graphics::denoiser::FeatureBuffers;
+use graphics::film::Film;
+use graphics::filter::Filter;
 use math_support::*;
 use world::World;
 
@@ -55,29 +58,81 @@ const ver: Vec3 = Vec3 {
     z: 0.0,
 };
 
+//--------------------------------    Environment    -------------------------------------
+const ENV_MAP_PATH: &str = ""; // Equirectangular .hdr or .pfm. Gradient sky if empty.
+const ENV_MAP_ROTATION: f64 = 0.0; // In degrees, around the y axis.
+const ENV_MAP_INTENSITY: f64 = 1.0;
+const USE_SKY_MODEL: bool = true; // Sun & sky instead of the gradient, if no environment map.
+const SUN_ELEVATION: f64 = 35.0; // In degrees, above the horizon.
+const SUN_AZIMUTH: f64 = 135.0; // In degrees, from +x towards +z.
+const TURBIDITY: f64 = 3.0; // From 2 (clear) to 10 (hazy).
+const SKY_EXPOSURE: f64 = 0.05; // The sky model is in kcd/m^2.
+
+//--------------------------------    Volume    ------------------------------------------
+const VOLUME_GRID_PATH: &str = ""; // Voxel grid of densities. Procedural cloud if empty.
+const VOLUME_DENSITY: f64 = 30.0; // Extinction per unit length of density 1.
+
+//--------------------------------    Texture    -----------------------------------------
+const NORMAL_MAP_PATH: &str = ""; // Tangent space normal map of the floor. Bumps of noise if empty.
+const NORMAL_MAP_STRENGTH: f64 = 0.05; // Tilt of the map, or the height of the bumps.
+
+//--------------------------------    Patches    -----------------------------------------
+const PATCHES_PATH: &str = ""; // Bicubic Bezier patches like the Utah teapot's. A vase if empty.
+const PATCHES_SCALE: f64 = 0.08; // Of the file, whose z is up. The teapot is 3.15 tall.
+
 //--------------------------------    World Settings    ----------------------------------
 lazy_static::lazy_static! {
     static ref lower_left_corner:Vec3 = origin - hor/2.0 - ver/2.0 - Vec3{x:0.0, y:0.0, z:VIEWPORT_DEPTH};
     static ref wld:World = World::make_world();
+    // Box, tent, gaussian, mitchell or lanczos.
+    static ref flt:Filter = Filter::make_mitchell(FILTER_RADIUS, 1.0 / 3.0, 1.0 / 3.0);
 }
 
 //--------------------------------     Render Parameters    ------------------------------
-const ITERATION_DEPTH: i32 = 50;
-const SAMPLES_PER_PIXEL: i32 = 100;
+const ITERATION_DEPTH: i32 = 50; // Hard limit of bounces.
+const RR_MIN_DEPTH: i32 = 3; // Bounces before russian roulette may terminate a path.
+const SSS_MAX_STEPS: i32 = 256; // Hard limit of scattering events inside media.
+const CUTOUT_MAX_LAYERS: i32 = 16; // Transparent hits a ray passes through on one obj.
+const MIN_SAMPLES_PER_PIXEL: i32 = 16;
+const MAX_SAMPLES_PER_PIXEL: i32 = 100;
+const NOISE_THRESHOLD: f64 = 0.01; // Relative standard error at which a pixel stops sampling.
+const OUTPUT_HEATMAP: bool = true; // Also output the samples taken per pixel.
+const FILTER_RADIUS: f64 = 2.0; // In pixels. Box filter of radius 0.5 averages inside the pixel.
+const DENOISE: bool = true; // Also output the denoised image.
+const SAVE_BUFFERS: bool = true; // Save color & feature buffers for `raytracer denoise`.
+const SPECTRAL_MODE: bool = false; // Trace 3 wavelengths per path instead of rgb.
 
 fn main() {
     //----------------------------------------    Init    --------------------------------
     let path = "output/output.jpg";
+    let heatmap_path = "output/heatmap.jpg";
+    let denoised_path = "output/denoised.jpg";
+    let buffers_path = "output/buffers.bin";
+
+    // `raytracer denoise [buffers] [output]` denoises saved buffers without rendering.
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() > 1 && args[1] == "denoise" {
+        let buffers_path = args.get(2).map_or(buffers_path, |s| s.as_str());
+        let denoised_path = args.get(3).map_or(denoised_path, |s| s.as_str());
+        run_denoise(buffers_path, denoised_path);
+    }
+
     print!("{}[2J", 27 as char); // Clear screen
     print!("{esc}[2J{esc}[1;1H", esc = 27 as char); // Set cursor position as 1,1
     println!(
         "Image size: {}\nJPEG quality: {}",
-        style(width.to_string() + &"x".to_string() + &height.to_string()).yellow(),
+        style(width.to_string() + "x" + &height.to_string()).yellow(),
         style(quality.to_string()).yellow(),
     );
 
     // Create image data
-    let mut img: RgbImage = ImageBuffer::new(width, height);
+    let mut film = Film::make_film(width, height);
+    let mut heat_img: RgbImage = if OUTPUT_HEATMAP {
+        ImageBuffer::new(width, height)
+    } else {
+        ImageBuffer::new(0, 0)
+    };
+    let mut total_samples: u64 = 0;
     // Progress bar UI powered by library `indicatif`
     // Get environment variable CI, which is true for GitHub Action
     let progress = if option_env!("CI").unwrap_or_default() == "true" {
@@ -93,18 +148,14 @@ fn main() {
 
     for y in 0..height {
         for x in 0..width {
-            // Do render with anti-aliasing and gamma-correction.
-            let color_vec: Vec3 = graphics::render_pixel(x, y);
-
-            // Image generating.
-            let pixel_color = [
-                (color_vec.x * 255.0).floor() as u8,
-                (color_vec.y * 255.0).floor() as u8,
-                (color_vec.z * 255.0).floor() as u8,
-            ];
-
-            let pixel = img.get_pixel_mut(x, height - y - 1);
-            *pixel = image::Rgb(pixel_color);
+            // Do render with adaptive anti-aliasing.
+            let samples = graphics::render_pixel(x, y, &mut film);
+            total_samples += samples as u64;
+
+            if OUTPUT_HEATMAP {
+                let pixel = heat_img.get_pixel_mut(x, height - y - 1);
+                *pixel = image::Rgb(to_pixel_color(graphics::sampler::heat_color(samples)));
+            }
             progress.inc(1);
         }
     }
@@ -113,7 +164,71 @@ fn main() {
 
     progress.finish();
 
+    let buffers = film.get_buffers();
+    println!(
+        "Average samples per pixel: {}",
+        style(format!(
+            "{:.1}",
+            total_samples as f64 / (width * height) as f64
+        ))
+        .yellow()
+    );
+
     // Output image to file
+    output_image(make_image(&buffers.color, width, height), path);
+    if OUTPUT_HEATMAP {
+        output_image(heat_img, heatmap_path);
+    }
+    if SAVE_BUFFERS {
+        println!("Ouput buffers as \"{}\"", style(buffers_path).yellow());
+        if buffers.save(buffers_path).is_err() {
+            println!("{}", style("Outputting buffers fails.").red());
+        }
+    }
+    if DENOISE {
+        output_image(make_image(&buffers.denoise(), width, height), denoised_path);
+    }
+
+    exit(0);
+}
+
+fn run_denoise(buffers_path: &str, denoised_path: &str) {
+    println!("Denoise buffers \"{}\"", style(buffers_path).yellow());
+    match FeatureBuffers::load(buffers_path) {
+        Ok(buffers) => {
+            let img = make_image(&buffers.denoise(), buffers.width, buffers.height);
+            output_image(img, denoised_path);
+            exit(0);
+        }
+        Err(err) => {
+            println!("{}", style(format!("Loading buffers fails: {}", err)).red());
+            exit(1);
+        }
+    }
+}
+
+fn make_image(colors: &[Vec3], img_width: u32, img_height: u32) -> RgbImage {
+    // Image generating with gamma-correction. Colors are stored from the bottom row.
+    let mut img: RgbImage = ImageBuffer::new(img_width, img_height);
+    for y in 0..img_height {
+        for x in 0..img_width {
+            let color_vec = colors[(y * img_width + x) as usize].sqrt_for_gamma_correction();
+            let pixel = img.get_pixel_mut(x, img_height - y - 1);
+            *pixel = image::Rgb(to_pixel_color(color_vec));
+        }
+    }
+    img
+}
+
+fn to_pixel_color(color_vec: Vec3) -> [u8; 3] {
+    [
+        (color_vec.x.min(1.0) * 255.0).floor() as u8,
+        (color_vec.y.min(1.0) * 255.0).floor() as u8,
+        (color_vec.z.min(1.0) * 255.0).floor() as u8,
+    ]
+}
+
+fn output_image(img: RgbImage, path: &str) {
     println!("Ouput image as \"{}\"", style(path).yellow());
     let output_image = image::DynamicImage::ImageRgb8(img);
     let mut output_file = File::create(path).unwrap();
@@ -121,6 +236,4 @@ fn main() {
         Ok(_) => {}
         Err(_) => println!("{}", style("Outputting image fails.").red()),
     }
-
-    exit(0);
 }
@@ -3,8 +3,10 @@
 //---------------------------    Module math_support    ----------------------------------------------------------
 
 use rand::Rng;
+use std::f64::consts::PI;
 use std::ops;
 pub const EPS: f64 = 0.0000001;
+const NOISE_OCTAVES: i32 = 4;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Vec3 {
@@ -112,6 +114,26 @@ impl Vec3 {
         self / len
     }
 
+    pub fn get(self, axis: usize) -> f64 {
+        match axis {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        }
+    }
+
+    pub fn get_max(self) -> f64 {
+        self.x.max(self.y).max(self.z)
+    }
+
+    pub fn max_with(self, val: f64) -> Vec3 {
+        Vec3::make_vec3(self.x.max(val), self.y.max(val), self.z.max(val))
+    }
+
+    pub fn exp(self) -> Vec3 {
+        Vec3::make_vec3(self.x.exp(), self.y.exp(), self.z.exp())
+    }
+
     pub fn sqrt_for_gamma_correction(self) -> Vec3 {
         Vec3::make_vec3(self.x.sqrt(), self.y.sqrt(), self.z.sqrt())
     }
@@ -139,6 +161,11 @@ pub fn close_to_unitary(val: Vec3) -> bool {
     close_to(val.get_len(), 1.0)
 }
 
+pub fn luminance(color: Vec3) -> f64 {
+    // Rec.709 luminance of a linear color.
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
 pub fn rand_0_1() -> f64 {
     // Random number between 0 & 1.
     let mut rng = rand::thread_rng();
@@ -159,6 +186,157 @@ pub fn rand_normalized_vec() -> Vec3 {
     res.normalize()
 }
 
+pub fn make_onb(normal: Vec3) -> (Vec3, Vec3) {
+    // Two unit tangents orthogonal to the normalized normal (Duff et al. 2017).
+    let sign = if normal.z >= 0.0 { 1.0 } else { -1.0 };
+    let a = -1.0 / (sign + normal.z);
+    let b = normal.x * normal.y * a;
+    (
+        Vec3::make_vec3(
+            1.0 + sign * normal.x * normal.x * a,
+            sign * b,
+            -sign * normal.x,
+        ),
+        Vec3::make_vec3(b, sign + normal.y * normal.y * a, -normal.y),
+    )
+}
+
+pub fn sample_cone(axis: Vec3, cos_max: f64) -> Vec3 {
+    // Uniform direction inside the cone, pdf is 1 / (2 * PI * (1 - cos_max)).
+    let cos_theta = 1.0 - rand_0_1() * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * rand_0_1();
+    let (t, b) = make_onb(axis);
+    (sin_theta * phi.cos()) * t + (sin_theta * phi.sin()) * b + cos_theta * axis
+}
+
+fn get_hash(i: i64, j: i64, k: i64) -> f64 {
+    // Lattice value in [0, 1).
+    let mut h = (i.wrapping_mul(73_856_093)
+        ^ j.wrapping_mul(19_349_663)
+        ^ k.wrapping_mul(83_492_791)) as u32;
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    h as f64 / (u32::MAX as f64 + 1.0)
+}
+
+pub fn get_value_noise(p: Vec3) -> f64 {
+    let (x, y, z) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+    let (fx, fy, fz) = (smooth(p.x - x), smooth(p.y - y), smooth(p.z - z));
+    let (i, j, k) = (x as i64, y as i64, z as i64);
+    let lerp = |a: f64, b: f64, t: f64| a * (1.0 - t) + b * t;
+    let face = |k: i64| {
+        lerp(
+            lerp(get_hash(i, j, k), get_hash(i + 1, j, k), fx),
+            lerp(get_hash(i, j + 1, k), get_hash(i + 1, j + 1, k), fx),
+            fy,
+        )
+    };
+    lerp(face(k), face(k + 1), fz)
+}
+
+pub fn get_fbm(p: Vec3) -> f64 {
+    // Fractal sum of octaves, in [0, 1).
+    let (mut res, mut amp, mut freq, mut total) = (0.0, 0.5, 1.0, 0.0);
+    for _ in 0..NOISE_OCTAVES {
+        res += amp * get_value_noise(freq * p);
+        total += amp;
+        amp *= 0.5;
+        freq *= 2.0;
+    }
+    res / total
+}
+
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    // Real roots of a x^2 + b x + c, in ascending order.
+    if a.abs() < EPS {
+        return if b.abs() < EPS { vec![] } else { vec![-c / b] };
+    }
+    let delta = b * b - 4.0 * a * c;
+    if delta < 0.0 {
+        return vec![];
+    }
+    // Avoids the cancellation of -b + sqrt(delta).
+    let q = -0.5 * (b + b.signum() * delta.sqrt());
+    let (x0, x1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    vec![x0.min(x1), x0.max(x1)]
+}
+
+pub fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    // Real roots of x^3 + a x^2 + b x + c, by the trigonometric method if there are three.
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    if r * r < q * q * q {
+        let theta = (r / (q * q * q).sqrt()).clamp(-1.0, 1.0).acos();
+        let m = -2.0 * q.sqrt();
+        vec![
+            m * (theta / 3.0).cos() - a / 3.0,
+            m * ((theta + 2.0 * PI) / 3.0).cos() - a / 3.0,
+            m * ((theta - 2.0 * PI) / 3.0).cos() - a / 3.0,
+        ]
+    } else {
+        let big_a = -r.signum() * (r.abs() + (r * r - q * q * q).sqrt()).cbrt();
+        let big_b = if big_a == 0.0 { 0.0 } else { q / big_a };
+        vec![big_a + big_b - a / 3.0]
+    }
+}
+
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // Real roots of x^4 + a x^3 + b x^2 + c x + d by Ferrari's method,
+    // polished by Newton's method on the quartic.
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = c - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * c / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+
+    // Roots of the depressed y^4 + p y^2 + q y + r, with x = y - a / 4.
+    let mut ys: Vec<f64> = Vec::new();
+    if q.abs() < 1e-12 {
+        for z in solve_quadratic(1.0, p, r) {
+            if z >= 0.0 {
+                ys.push(z.sqrt());
+                ys.push(-z.sqrt());
+            }
+        }
+    } else {
+        // The resolvent cubic has a positive root, which splits the quartic in two quadratics.
+        let m = solve_cubic(p, p * p / 4.0 - r, -q * q / 8.0)
+            .into_iter()
+            .fold(0.0, f64::max);
+        if m <= 0.0 {
+            return vec![];
+        }
+        let s = (2.0 * m).sqrt();
+        ys.extend(solve_quadratic(1.0, -s, p / 2.0 + m + q / (2.0 * s)));
+        ys.extend(solve_quadratic(1.0, s, p / 2.0 + m - q / (2.0 * s)));
+    }
+
+    ys.iter()
+        .map(|y| {
+            let mut x = y - a / 4.0;
+            for _ in 0..2 {
+                let f = (((x + a) * x + b) * x + c) * x + d;
+                let df = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+                if df.abs() > EPS {
+                    x -= f / df;
+                }
+            }
+            x
+        })
+        .collect()
+}
+
+pub fn xyz_to_rgb(xyz: Vec3) -> Vec3 {
+    // CIE XYZ to linear sRGB.
+    Vec3::make_vec3(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    )
+}
+
 pub fn is_front_face(dir: Vec3, normal: Vec3) -> bool {
     dot(dir, normal) < EPS
 }
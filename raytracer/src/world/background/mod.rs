@@ -0,0 +1,288 @@
+// Mod Background: what a ray sees when it hits nothing.
+pub mod sky;
+
+use crate::math_support::*;
+use image::codecs::hdr::HdrDecoder;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+pub use sky::SkyModel;
+
+//---------------------------    Enum Background    ---------------------------------------
+pub enum Background {
+    Gradient,
+    Env(EnvMap),
+    Sky(SkyModel),
+}
+
+impl Background {
+    pub fn get_radiance(&self, dir: Vec3) -> Vec3 {
+        match self {
+            Background::Gradient => {
+                let p: f64 = 0.5 * (dir.y + 1.0);
+                (1.0 - p) * Vec3::make_vec3(1.0, 1.0, 1.0) + p * Vec3::make_vec3(0.5, 0.7, 1.0)
+            }
+            Background::Env(tmp) => tmp.get_radiance(dir),
+            Background::Sky(tmp) => tmp.get_radiance(dir),
+        }
+    }
+
+    pub fn can_sample(&self) -> bool {
+        // Whether the background should be sampled as a light.
+        match self {
+            Background::Gradient => false,
+            Background::Env(tmp) => true,
+            Background::Sky(tmp) => true,
+        }
+    }
+
+    pub fn sample_dir(&self) -> (Vec3, f64) {
+        // Direction towards the background and its solid angle pdf.
+        match self {
+            Background::Gradient => (Vec3::make_vec3(0.0, 1.0, 0.0), 0.0),
+            Background::Env(tmp) => tmp.sample_dir(),
+            Background::Sky(tmp) => tmp.sample_dir(),
+        }
+    }
+
+    pub fn get_pdf(&self, dir: Vec3) -> f64 {
+        match self {
+            Background::Gradient => 0.0,
+            Background::Env(tmp) => tmp.get_pdf(dir),
+            Background::Sky(tmp) => tmp.get_pdf(dir),
+        }
+    }
+}
+
+//------------------------------    Struct EnvMap    --------------------------------------
+// Equirectangular environment map, importance sampled by luminance
+// with a marginal cdf over rows and a conditional cdf inside each row.
+
+pub struct EnvMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>, // From the top row, which is +y.
+    rotation: f64,     // Radians around the y axis.
+    intensity: f64,
+    func: Vec<f64>,            // Sampling weight of each pixel.
+    conditional_cdf: Vec<f64>, // height rows of (width + 1) values.
+    marginal_cdf: Vec<f64>,    // height + 1 values.
+    func_integral: f64,
+}
+
+impl EnvMap {
+    pub fn make_env_map(path: &str, rotation: f64, intensity: f64) -> Result<EnvMap, String> {
+        // Rotation is in degrees.
+        let (width, height, pixels) = if path.to_lowercase().ends_with(".pfm") {
+            EnvMap::load_pfm(path)?
+        } else {
+            EnvMap::load_hdr(path)?
+        };
+        if width == 0 || height == 0 {
+            return Err(format!("{} is empty", path));
+        }
+
+        let mut res = EnvMap {
+            width,
+            height,
+            pixels,
+            rotation: rotation.to_radians(),
+            intensity,
+            func: vec![0.0; width * height],
+            conditional_cdf: vec![0.0; (width + 1) * height],
+            marginal_cdf: vec![0.0; height + 1],
+            func_integral: 0.0,
+        };
+        res.build_distribution();
+        Ok(res)
+    }
+
+    fn load_hdr(path: &str) -> Result<(usize, usize, Vec<Vec3>), String> {
+        let file = File::open(path).map_err(|err| err.to_string())?;
+        let decoder = HdrDecoder::new(BufReader::new(file)).map_err(|err| err.to_string())?;
+        let meta = decoder.metadata();
+        let pixels = decoder
+            .read_image_hdr()
+            .map_err(|err| err.to_string())?
+            .iter()
+            .map(|p| Vec3::make_vec3(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+        Ok((meta.width as usize, meta.height as usize, pixels))
+    }
+
+    fn load_pfm(path: &str) -> Result<(usize, usize, Vec<Vec3>), String> {
+        // "PF" for rgb or "Pf" for gray, then width, height and scale in ascii,
+        // whose sign tells the endianness. Rows are stored from the bottom.
+        let mut data = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|err| err.to_string())?;
+
+        let mut header: Vec<String> = Vec::new();
+        let mut at = 0;
+        while header.len() < 4 {
+            while at < data.len() && data[at].is_ascii_whitespace() {
+                at += 1;
+            }
+            let start = at;
+            while at < data.len() && !data[at].is_ascii_whitespace() {
+                at += 1;
+            }
+            if start == at {
+                return Err(format!("{} has a truncated pfm header", path));
+            }
+            header.push(String::from_utf8_lossy(&data[start..at]).to_string());
+        }
+        at += 1; // Single whitespace before the raster.
+
+        let channels = match header[0].as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return Err(format!("{} is not a pfm file", path)),
+        };
+        let parse_err = format!("{} has an invalid pfm header", path);
+        let width: usize = header[1].parse().map_err(|_| parse_err.clone())?;
+        let height: usize = header[2].parse().map_err(|_| parse_err.clone())?;
+        let scale: f64 = header[3].parse().map_err(|_| parse_err)?;
+
+        let len = width
+            .checked_mul(height)
+            .and_then(|size| size.checked_mul(channels * 4))
+            .and_then(|len| len.checked_add(at));
+        match len {
+            Some(len) if len <= data.len() => {}
+            _ => return Err(format!("{} is truncated", path)),
+        }
+        let read_f64 = |id: usize| {
+            let b = [
+                data[at + id * 4],
+                data[at + id * 4 + 1],
+                data[at + id * 4 + 2],
+                data[at + id * 4 + 3],
+            ];
+            if scale < 0.0 {
+                f32::from_le_bytes(b) as f64
+            } else {
+                f32::from_be_bytes(b) as f64
+            }
+        };
+
+        let mut pixels = vec![Vec3::make_vec3(0.0, 0.0, 0.0); width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let id = ((height - 1 - y) * width + x) * channels;
+                pixels[y * width + x] = if channels == 3 {
+                    Vec3::make_vec3(read_f64(id), read_f64(id + 1), read_f64(id + 2))
+                } else {
+                    Vec3::make_vec3(read_f64(id), read_f64(id), read_f64(id))
+                };
+            }
+        }
+        Ok((width, height, pixels))
+    }
+
+    fn build_distribution(&mut self) {
+        let (w, h) = (self.width, self.height);
+        for y in 0..h {
+            // Rows near the poles cover less solid angle.
+            let sin_theta = (PI * (y as f64 + 0.5) / h as f64).sin();
+            let cdf = &mut self.conditional_cdf[y * (w + 1)..(y + 1) * (w + 1)];
+            for x in 0..w {
+                let f = luminance(self.pixels[y * w + x]).max(0.0) * sin_theta;
+                self.func[y * w + x] = f;
+                cdf[x + 1] = cdf[x] + f / w as f64;
+            }
+            let row_integral = cdf[w];
+            normalize_cdf(cdf, row_integral);
+            self.marginal_cdf[y + 1] = self.marginal_cdf[y] + row_integral / h as f64;
+        }
+        self.func_integral = self.marginal_cdf[h];
+        let total = self.func_integral;
+        normalize_cdf(&mut self.marginal_cdf, total);
+    }
+
+    fn to_uv(&self, dir: Vec3) -> (f64, f64) {
+        let dir = dir.normalize();
+        let theta = dir.y.clamp(-1.0, 1.0).acos();
+        let phi = dir.z.atan2(dir.x) - self.rotation;
+        let u = (phi / (2.0 * PI)).rem_euclid(1.0);
+        (u, theta / PI)
+    }
+
+    fn to_dir(&self, u: f64, v: f64) -> Vec3 {
+        let theta = v * PI;
+        let phi = u * 2.0 * PI + self.rotation;
+        Vec3::make_vec3(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+
+    fn to_pixel_id(&self, u: f64, v: f64) -> usize {
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        y * self.width + x
+    }
+
+    pub fn get_radiance(&self, dir: Vec3) -> Vec3 {
+        let (u, v) = self.to_uv(dir);
+        self.intensity * self.pixels[self.to_pixel_id(u, v)]
+    }
+
+    pub fn sample_dir(&self) -> (Vec3, f64) {
+        if self.func_integral <= 0.0 {
+            return (rand_normalized_vec(), 1.0 / (4.0 * PI));
+        }
+        let w = self.width;
+        let y = find_interval(&self.marginal_cdf, rand_0_1());
+        let v = (y as f64 + rand_0_1()) / self.height as f64;
+        let x = find_interval(
+            &self.conditional_cdf[y * (w + 1)..(y + 1) * (w + 1)],
+            rand_0_1(),
+        );
+        let u = (x as f64 + rand_0_1()) / w as f64;
+
+        let dir = self.to_dir(u, v);
+        (dir, self.get_pdf(dir))
+    }
+
+    pub fn get_pdf(&self, dir: Vec3) -> f64 {
+        if self.func_integral <= 0.0 {
+            return 1.0 / (4.0 * PI);
+        }
+        let (u, v) = self.to_uv(dir);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        // From the pdf over the unit square to the pdf over solid angle.
+        self.func[self.to_pixel_id(u, v)] / self.func_integral / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+fn normalize_cdf(cdf: &mut [f64], total: f64) {
+    let n = cdf.len() - 1;
+    for (i, val) in cdf.iter_mut().enumerate() {
+        *val = if total > 0.0 {
+            *val / total
+        } else {
+            i as f64 / n as f64 // Uniform if all black.
+        };
+    }
+}
+
+fn find_interval(cdf: &[f64], val: f64) -> usize {
+    // Largest i that cdf[i] <= val, inside [0, len - 2].
+    let (mut lo, mut hi) = (0, cdf.len() - 1);
+    while hi - lo > 1 {
+        let mid = (lo + hi) / 2;
+        if cdf[mid] <= val {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
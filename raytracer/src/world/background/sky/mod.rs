@@ -0,0 +1,165 @@
+//----------------------------    Struct SkyModel    -------------------------------------
+// Preetham et al. 1999 analytic daylight, in kcd/m^2 before exposure,
+// plus a sun disk attenuated by Rayleigh and aerosol scattering.
+use crate::math_support::*;
+use std::f64::consts::PI;
+
+const SUN_ANGULAR_RADIUS: f64 = 0.004_65; // Radians.
+const SUN_LUMINANCE: f64 = 1.6e6; // kcd/m^2 outside the atmosphere.
+const SUN_SAMPLE_PROB: f64 = 0.5; // Probability of sampling the sun disk instead of the sky.
+const GROUND_RATIO: f64 = 0.3; // Below the horizon, a dim copy of the horizon.
+
+pub struct SkyModel {
+    sun_dir: Vec3,
+    sun_radiance: Vec3,
+    exposure: f64,
+    zenith: Vec3,         // (Y, x, y) at zenith.
+    perez: [[f64; 5]; 3], // A to E for Y, x and y.
+    perez_norm: [f64; 3], // F(0, theta_sun) for Y, x and y.
+}
+
+impl SkyModel {
+    pub fn make_sky(elevation: f64, azimuth: f64, turbidity: f64, exposure: f64) -> SkyModel {
+        // Angles in degrees. Azimuth goes from +x to +z.
+        let (e, a) = (elevation.to_radians(), azimuth.to_radians());
+        let sun_dir = Vec3::make_vec3(e.cos() * a.cos(), e.sin(), e.cos() * a.sin());
+        let theta_s = (PI / 2.0 - e).max(0.0);
+        let t = turbidity;
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_lum = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let th = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+        let chroma = |m: [[f64; 4]; 3]| {
+            let row = |r: [f64; 4]| r[0] * th[0] + r[1] * th[1] + r[2] * th[2] + r[3] * th[3];
+            t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+        };
+        let zenith_x = chroma([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = chroma([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+        let perez_norm = [
+            perez_f(&perez[0], 0.0, theta_s),
+            perez_f(&perez[1], 0.0, theta_s),
+            perez_f(&perez[2], 0.0, theta_s),
+        ];
+
+        SkyModel {
+            sun_dir,
+            sun_radiance: SUN_LUMINANCE * sun_transmittance(theta_s, t),
+            exposure,
+            zenith: Vec3::make_vec3(zenith_lum, zenith_x, zenith_y),
+            perez,
+            perez_norm,
+        }
+    }
+
+    fn get_sky_radiance(&self, dir: Vec3) -> Vec3 {
+        // Sky only, without the sun disk.
+        let ground = dir.y < 0.0;
+        let dir = if ground {
+            Vec3::make_vec3(dir.x, 0.0, dir.z).normalize()
+        } else {
+            dir
+        };
+        let theta = dir.y.clamp(0.0, 1.0).acos().min(PI / 2.0 - 0.001);
+        let gamma = dot(dir, self.sun_dir).clamp(-1.0, 1.0).acos();
+
+        let lum = self.zenith.x * perez_f(&self.perez[0], theta, gamma) / self.perez_norm[0];
+        let x = self.zenith.y * perez_f(&self.perez[1], theta, gamma) / self.perez_norm[1];
+        let y = self.zenith.z * perez_f(&self.perez[2], theta, gamma) / self.perez_norm[2];
+        if y <= 0.0 {
+            return Vec3::make_vec3(0.0, 0.0, 0.0);
+        }
+        let xyz = Vec3::make_vec3(x * lum / y, lum, (1.0 - x - y) * lum / y);
+        let rgb = xyz_to_rgb(xyz).max_with(0.0);
+        if ground {
+            GROUND_RATIO * rgb
+        } else {
+            rgb
+        }
+    }
+
+    fn is_in_sun(&self, dir: Vec3) -> bool {
+        dot(dir, self.sun_dir) >= SUN_ANGULAR_RADIUS.cos() && self.sun_dir.y > 0.0
+    }
+
+    pub fn get_radiance(&self, dir: Vec3) -> Vec3 {
+        let dir = dir.normalize();
+        let mut res = self.get_sky_radiance(dir);
+        if self.is_in_sun(dir) {
+            res = res + self.sun_radiance;
+        }
+        self.exposure * res
+    }
+
+    pub fn sample_dir(&self) -> (Vec3, f64) {
+        let dir = if self.sun_dir.y > 0.0 && rand_0_1() < SUN_SAMPLE_PROB {
+            sample_cone(self.sun_dir, SUN_ANGULAR_RADIUS.cos())
+        } else {
+            rand_normalized_vec()
+        };
+        (dir, self.get_pdf(dir))
+    }
+
+    pub fn get_pdf(&self, dir: Vec3) -> f64 {
+        let uniform_pdf = 1.0 / (4.0 * PI);
+        if self.sun_dir.y <= 0.0 {
+            return uniform_pdf;
+        }
+        let sun_pdf = if self.is_in_sun(dir.normalize()) {
+            1.0 / (2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.cos()))
+        } else {
+            0.0
+        };
+        SUN_SAMPLE_PROB * sun_pdf + (1.0 - SUN_SAMPLE_PROB) * uniform_pdf
+    }
+}
+
+fn perez_f(coef: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coef;
+    (1.0 + a * (b / theta.cos().max(0.01)).exp())
+        * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+}
+
+fn sun_transmittance(theta_s: f64, turbidity: f64) -> Vec3 {
+    // Rayleigh and Angstrom aerosol extinction at 650, 550 and 450 nm.
+    let theta_deg = theta_s.to_degrees();
+    let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_deg).max(0.01).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let channel = |lambda: f64| {
+        let rayleigh = 0.008_735 * lambda.powf(-4.08);
+        let aerosol = beta * lambda.powf(-1.3);
+        (-air_mass * (rayleigh + aerosol)).exp()
+    };
+    Vec3::make_vec3(channel(0.65), channel(0.55), channel(0.45))
+}
@@ -0,0 +1,140 @@
+// Mod Light: punctual lights, which are not geometry and can only be reached by shadow rays.
+pub mod tree;
+
+use crate::math_support::*;
+use std::f64::consts::PI;
+
+//---------------------------    Struct PointLight    ------------------------------------
+
+pub struct PointLight {
+    pos: Vec3,
+    intensity: Vec3, // Radiant intensity, falls off with inverse square of the distance.
+}
+
+impl PointLight {
+    pub fn make_point(pos: Vec3, intensity: Vec3) -> PointLight {
+        PointLight { pos, intensity }
+    }
+}
+
+//---------------------------    Struct SpotLight    -------------------------------------
+
+pub struct SpotLight {
+    pos: Vec3,
+    dir: Vec3,
+    intensity: Vec3,
+    cos_inner: f64, // Full intensity inside the inner cone.
+    cos_outer: f64, // No light outside the outer cone.
+}
+
+impl SpotLight {
+    pub fn make_spot(pos: Vec3, dir: Vec3, intensity: Vec3, inner: f64, outer: f64) -> SpotLight {
+        // Cone half angles in degrees.
+        SpotLight {
+            pos,
+            dir: dir.normalize(),
+            intensity,
+            cos_inner: inner.to_radians().cos(),
+            cos_outer: outer.max(inner).to_radians().cos(),
+        }
+    }
+
+    fn get_falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            1.0
+        } else if cos_theta <= self.cos_outer {
+            0.0
+        } else {
+            // Smoothstep between the two cones.
+            let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+//------------------------    Struct DirectionalLight    ---------------------------------
+
+pub struct DirectionalLight {
+    dir: Vec3,        // The direction light travels along.
+    irradiance: Vec3, // On a surface facing the light.
+}
+
+impl DirectionalLight {
+    pub fn make_directional(dir: Vec3, irradiance: Vec3) -> DirectionalLight {
+        DirectionalLight {
+            dir: dir.normalize(),
+            irradiance,
+        }
+    }
+}
+
+//-----------------------------    Enum Light    ------------------------------------------
+
+pub enum Light {
+    Point(PointLight),
+    Spot(SpotLight),
+    Dir(DirectionalLight),
+}
+
+impl Light {
+    pub fn make_light_point(pos: Vec3, intensity: Vec3) -> Light {
+        Light::Point(PointLight::make_point(pos, intensity))
+    }
+
+    pub fn make_light_spot(pos: Vec3, dir: Vec3, intensity: Vec3, inner: f64, outer: f64) -> Light {
+        Light::Spot(SpotLight::make_spot(pos, dir, intensity, inner, outer))
+    }
+
+    pub fn make_light_dir(dir: Vec3, irradiance: Vec3) -> Light {
+        Light::Dir(DirectionalLight::make_directional(dir, irradiance))
+    }
+
+    pub fn sample_li(&self, pos: Vec3) -> (Vec3, f64, Vec3) {
+        // Direction to the light, distance to the light and incident radiance at pos.
+        match self {
+            Light::Point(tmp) => {
+                let to_light = tmp.pos - pos;
+                let dist = to_light.get_len();
+                (to_light / dist, dist, tmp.intensity / (dist * dist))
+            }
+            Light::Spot(tmp) => {
+                let to_light = tmp.pos - pos;
+                let dist = to_light.get_len();
+                let dir = to_light / dist;
+                let falloff = tmp.get_falloff(-dot(dir, tmp.dir));
+                (dir, dist, (falloff / (dist * dist)) * tmp.intensity)
+            }
+            Light::Dir(tmp) => (
+                Vec3::make_vec3(0.0, 0.0, 0.0) - tmp.dir,
+                f64::INFINITY,
+                tmp.irradiance,
+            ),
+        }
+    }
+
+    pub fn get_position(&self) -> Option<Vec3> {
+        // None for lights at infinity.
+        match self {
+            Light::Point(tmp) => Some(tmp.pos),
+            Light::Spot(tmp) => Some(tmp.pos),
+            Light::Dir(tmp) => None,
+        }
+    }
+
+    pub fn get_power(&self) -> f64 {
+        match self {
+            Light::Point(tmp) => 4.0 * PI * luminance(tmp.intensity),
+            Light::Spot(tmp) => 2.0 * PI * (1.0 - tmp.cos_outer) * luminance(tmp.intensity),
+            Light::Dir(tmp) => luminance(tmp.irradiance),
+        }
+    }
+
+    pub fn get_emit_cone(&self) -> (Vec3, f64) {
+        // Axis and half angle bounding the emitted directions.
+        match self {
+            Light::Spot(tmp) => (tmp.dir, tmp.cos_outer.acos()),
+            Light::Dir(tmp) => (tmp.dir, 0.0),
+            _ => (Vec3::make_vec3(0.0, 1.0, 0.0), PI),
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//-----------------------------    Struct LightTree    ------------------------------------
+// Light hierarchy of Conty Estevez & Kulla 2018. Every node bounds its lights by
+// a box, an orientation cone and the total power, from which the importance of
+// the node to a shading point is estimated. Lights are picked by walking down
+// the tree, choosing each child proportional to its importance.
+use crate::entity::aabb::Aabb;
+use crate::entity::*;
+use crate::math_support::*;
+use crate::world::light::Light;
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy)]
+pub enum LightRef {
+    Punctual(usize), // Index in the light list of the world.
+    Emitter(usize),  // Index in the obj list of the world.
+}
+
+//-------------------------------    Struct Cone    ---------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+struct Cone {
+    axis: Vec3,
+    theta_o: f64, // Bounds the normals (or the emitting directions).
+    theta_e: f64, // Bounds the emission around each normal.
+}
+
+impl Cone {
+    fn surround(&self, other: &Cone) -> Cone {
+        let (a, b) = if self.theta_o >= other.theta_o {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let theta_d = dot(a.axis, b.axis).clamp(-1.0, 1.0).acos();
+        let theta_e = a.theta_e.max(b.theta_e);
+        if (theta_d + b.theta_o).min(PI) <= a.theta_o {
+            return Cone {
+                axis: a.axis,
+                theta_o: a.theta_o,
+                theta_e,
+            };
+        }
+        let theta_o = (a.theta_o + theta_d + b.theta_o) / 2.0;
+        let rot_axis = cross(a.axis, b.axis);
+        if theta_o >= PI || rot_axis.get_len() < EPS {
+            return Cone {
+                axis: a.axis,
+                theta_o: PI,
+                theta_e,
+            };
+        }
+        // Rotate a's axis towards b's axis (Rodrigues' rotation).
+        let k = rot_axis.normalize();
+        let theta_r = theta_o - a.theta_o;
+        let axis = theta_r.cos() * a.axis
+            + theta_r.sin() * cross(k, a.axis)
+            + ((1.0 - theta_r.cos()) * dot(k, a.axis)) * k;
+        Cone {
+            axis: axis.normalize(),
+            theta_o,
+            theta_e,
+        }
+    }
+}
+
+//-------------------------------    Struct LightNode    ---------------------------------
+
+struct LightNode {
+    bbox: Aabb,
+    cone: Cone,
+    power: f64,
+    start: usize, // Lights of the node are lights[start..end].
+    end: usize,
+    children: Option<(usize, usize)>,
+}
+
+impl LightNode {
+    fn get_importance(&self, pos: Vec3, normal: Vec3) -> f64 {
+        // Estimated contribution of the node to a surface at pos with normal,
+        // which is 0 if the node surely cannot light it.
+        if self.power <= 0.0 {
+            return 0.0;
+        }
+        let centre = self.bbox.get_centre();
+        let radius = self.bbox.get_diagonal().get_len() / 2.0;
+        let to_node = centre - pos;
+        let dist2 = dot(to_node, to_node);
+        if self.bbox.contains(pos) || dist2 <= radius * radius {
+            return self.power;
+        }
+        let dist = dist2.sqrt();
+        let dir = to_node / dist;
+        let theta_u = (radius / dist).asin(); // Half angle the node subtends.
+
+        // Angle between the emitting cone and the direction to pos.
+        let theta = dot(self.cone.axis, crate::origin - dir)
+            .clamp(-1.0, 1.0)
+            .acos();
+        let theta_p = (theta - self.cone.theta_o - theta_u).max(0.0);
+        if theta_p > self.cone.theta_e {
+            return 0.0;
+        }
+        // Angle between the surface normal and the direction to the node,
+        // which does not matter inside media, where the normal is 0.
+        let theta_ip = if normal.get_len() < EPS {
+            0.0
+        } else {
+            let theta_i = dot(normal, dir).abs().clamp(-1.0, 1.0).acos();
+            (theta_i - theta_u).max(0.0)
+        };
+
+        let min_dist2 = (radius * radius).max(EPS);
+        self.power * theta_ip.cos() * theta_p.cos() / dist2.max(min_dist2)
+    }
+}
+
+//-------------------------------    Struct LightTree    ---------------------------------
+
+pub struct LightTree {
+    lights: Vec<LightRef>,           // In the order of the leaves.
+    nodes: Vec<LightNode>,           // The root is nodes[0].
+    slot_of_obj: Vec<Option<usize>>, // From obj index to index in lights.
+}
+
+impl LightTree {
+    pub fn make_tree(obj_list: &[Entity], light_list: &[Light]) -> LightTree {
+        // Punctual lights at infinity are not in the tree.
+        let mut leaves: Vec<(LightRef, LightNode)> = Vec::new();
+        for (id, light) in light_list.iter().enumerate() {
+            if let Some(pos) = light.get_position() {
+                let (axis, theta_o) = light.get_emit_cone();
+                leaves.push((
+                    LightRef::Punctual(id),
+                    LightNode {
+                        bbox: Aabb::make_aabb(pos, pos),
+                        cone: Cone {
+                            axis,
+                            theta_o,
+                            theta_e: 0.0,
+                        },
+                        power: light.get_power(),
+                        start: 0,
+                        end: 0,
+                        children: None,
+                    },
+                ));
+            }
+        }
+        for (id, obj) in obj_list.iter().enumerate() {
+            let material = match obj.get_material() {
+                Some(material) => material,
+                None => continue,
+            };
+            let emit = luminance(material.get_emit());
+            let bbox = match obj.get_bounding_box() {
+                Some(bbox) => bbox,
+                None => continue,
+            };
+            if emit <= 0.0 || obj.get_area() <= 0.0 {
+                continue;
+            }
+            let (axis, theta_o) = obj.get_normal_cone();
+            leaves.push((
+                LightRef::Emitter(id),
+                LightNode {
+                    bbox,
+                    cone: Cone {
+                        axis,
+                        theta_o,
+                        theta_e: PI / 2.0,
+                    },
+                    power: PI * emit * obj.get_area(),
+                    start: 0,
+                    end: 0,
+                    children: None,
+                },
+            ));
+        }
+
+        let mut res = LightTree {
+            lights: Vec::new(),
+            nodes: Vec::new(),
+            slot_of_obj: vec![None; obj_list.len()],
+        };
+        if !leaves.is_empty() {
+            res.build(leaves);
+        }
+        for (slot, light) in res.lights.iter().enumerate() {
+            if let LightRef::Emitter(id) = light {
+                res.slot_of_obj[*id] = Some(slot);
+            }
+        }
+        res
+    }
+
+    fn build(&mut self, mut leaves: Vec<(LightRef, LightNode)>) -> usize {
+        // Returns the index of the new node.
+        let node_id = self.nodes.len();
+        if leaves.len() == 1 {
+            let (light, mut node) = leaves.pop().unwrap();
+            node.start = self.lights.len();
+            node.end = node.start + 1;
+            self.lights.push(light);
+            self.nodes.push(node);
+            return node_id;
+        }
+
+        // Split at the median of the centres, along the longest axis.
+        let mut centre_box =
+            Aabb::make_aabb(leaves[0].1.bbox.get_centre(), leaves[0].1.bbox.get_centre());
+        for (_, node) in &leaves {
+            let c = node.bbox.get_centre();
+            centre_box = centre_box.surround(&Aabb::make_aabb(c, c));
+        }
+        let axis = centre_box.get_longest_axis();
+        leaves.sort_by(|a, b| {
+            let ca = a.1.bbox.get_centre().get(axis);
+            let cb = b.1.bbox.get_centre().get(axis);
+            ca.partial_cmp(&cb).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let right_leaves = leaves.split_off(leaves.len() / 2);
+
+        // Placeholder, filled after the children are built.
+        self.nodes.push(LightNode {
+            bbox: leaves[0].1.bbox,
+            cone: leaves[0].1.cone,
+            power: 0.0,
+            start: self.lights.len(),
+            end: 0,
+            children: None,
+        });
+        let left = self.build(leaves);
+        let right = self.build(right_leaves);
+
+        let (l, r) = (&self.nodes[left], &self.nodes[right]);
+        let (bbox, cone, power, end) = (
+            l.bbox.surround(&r.bbox),
+            l.cone.surround(&r.cone),
+            l.power + r.power,
+            r.end,
+        );
+        let node = &mut self.nodes[node_id];
+        node.bbox = bbox;
+        node.cone = cone;
+        node.power = power;
+        node.end = end;
+        node.children = Some((left, right));
+        node_id
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn get_light(&self, slot: usize) -> LightRef {
+        self.lights[slot]
+    }
+
+    pub fn get_slot_of_obj(&self, id: usize) -> Option<usize> {
+        self.slot_of_obj[id]
+    }
+
+    pub fn sample(&self, pos: Vec3, normal: Vec3) -> Option<(usize, f64)> {
+        // A light slot and the probability of choosing it.
+        if self.is_empty() || self.nodes[0].get_importance(pos, normal) <= 0.0 {
+            return None;
+        }
+        let mut node_id = 0;
+        let mut pdf = 1.0;
+        while let Some((left, right)) = self.nodes[node_id].children {
+            let il = self.nodes[left].get_importance(pos, normal);
+            let ir = self.nodes[right].get_importance(pos, normal);
+            if il + ir <= 0.0 {
+                return None;
+            }
+            let pl = il / (il + ir);
+            if rand_0_1() < pl {
+                node_id = left;
+                pdf *= pl;
+            } else {
+                node_id = right;
+                pdf *= 1.0 - pl;
+            }
+        }
+        Some((self.nodes[node_id].start, pdf))
+    }
+
+    pub fn get_pdf(&self, pos: Vec3, normal: Vec3, slot: usize) -> f64 {
+        // Probability of sample choosing the light slot.
+        if self.is_empty() || self.nodes[0].get_importance(pos, normal) <= 0.0 {
+            return 0.0;
+        }
+        let mut node_id = 0;
+        let mut pdf = 1.0;
+        while let Some((left, right)) = self.nodes[node_id].children {
+            let il = self.nodes[left].get_importance(pos, normal);
+            let ir = self.nodes[right].get_importance(pos, normal);
+            if il + ir <= 0.0 {
+                return 0.0;
+            }
+            if slot < self.nodes[left].end {
+                node_id = left;
+                pdf *= il / (il + ir);
+            } else {
+                node_id = right;
+                pdf *= ir / (il + ir);
+            }
+        }
+        pdf
+    }
+}
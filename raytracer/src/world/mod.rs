@@ -1,26 +1,54 @@
 // Struct World to store all objs.
 #![allow(unused_variables)]
 
+pub mod background;
+pub mod light;
+
+use crate::entity::aabb::Aabb;
+use crate::entity::bezier::BezierMesh;
+use crate::entity::csg::{Csg, CsgOp};
 use crate::entity::material::*;
+use crate::entity::sdf::{Implicit, Sdf};
+use crate::entity::shape::{Capsule, Cone, Cylinder, Torus};
+use crate::entity::volume::{Grid, Volume};
 use crate::entity::*;
 use crate::graphics::ray::Ray;
+use crate::graphics::spectrum::*;
 use crate::math_support::*;
+use background::*;
+use light::tree::{LightRef, LightTree};
+use light::Light;
 
 use crate::math_support::EPS;
 use crate::origin;
+use crate::CUTOUT_MAX_LAYERS;
 use crate::ITERATION_DEPTH;
+use crate::RR_MIN_DEPTH;
+use crate::SSS_MAX_STEPS;
 use crate::VIEWPORT_DEPTH;
+use crate::{ENV_MAP_INTENSITY, ENV_MAP_PATH, ENV_MAP_ROTATION};
+use crate::{NORMAL_MAP_PATH, NORMAL_MAP_STRENGTH};
+use crate::{PATCHES_PATH, PATCHES_SCALE};
+use crate::{SKY_EXPOSURE, SUN_AZIMUTH, SUN_ELEVATION, TURBIDITY, USE_SKY_MODEL};
+use crate::{VOLUME_DENSITY, VOLUME_GRID_PATH};
 
 //-------------------------------    Struct World    -------------------------------------
 
 pub struct World {
     pub obj_list: Vec<Entity>,
+    pub background: Background,
+    pub light_list: Vec<Light>,
+    pub light_tree: LightTree,
 }
 
 impl World {
     pub fn make_world() -> World {
         let new_list: Vec<Entity> = vec![
-            Entity::Pln(Plain::make_plain(-0.3, Mat::make_mat_lmb(0.5, 0.7, 0.6))),
+            Entity::Pln(Plain::make_plain(
+                Vec3::make_vec3(0.0, -0.3, 0.0),
+                Vec3::make_vec3(0.0, 1.0, 0.0),
+                Mat::make_mat_mapped(Mat::make_mat_lmb(0.5, 0.7, 0.6), World::make_floor_map()),
+            )),
             Entity::Sph(Sphere::make_sphere(
                 origin - Vec3::make_vec3(0.0, 0.0, VIEWPORT_DEPTH),
                 0.3,
@@ -37,45 +65,688 @@ impl World {
                 0.3,
                 Mat::make_mat_detc(1.5),
             )),
+            Entity::Sph(Sphere::make_sphere(
+                origin - Vec3::make_vec3(0.0, 0.0, VIEWPORT_DEPTH)
+                    + Vec3::make_vec3(0.0, -0.15, 0.45),
+                0.15,
+                Mat::make_mat_sss(
+                    1.4,
+                    Vec3::make_vec3(0.08, 0.04, 0.02),
+                    Vec3::make_vec3(0.99, 0.95, 0.9),
+                    Phase::Iso,
+                ),
+            )),
+            Entity::Sph(Sphere::make_sphere(
+                origin - Vec3::make_vec3(0.0, 0.0, VIEWPORT_DEPTH)
+                    + Vec3::make_vec3(0.3, -0.22, 0.4),
+                0.08,
+                Mat::make_mat_emit(8.0, 3.0, 1.0),
+            )),
+            Entity::Sph(Sphere::make_sphere(
+                origin - Vec3::make_vec3(0.0, 0.0, VIEWPORT_DEPTH)
+                    + Vec3::make_vec3(-0.35, -0.22, 0.35),
+                0.08,
+                Mat::make_mat_emit(1.0, 3.0, 8.0),
+            )),
+            // A row of the materials, floating above the scene.
+            World::make_showcase(-2.7, Mat::Cond(Conductor::make_gold(0.3))),
+            World::make_showcase(-1.8, Mat::make_mat_rough(1.5, 0.2)),
+            World::make_showcase(
+                -0.9,
+                Mat::make_mat_rough_tinted(1.5, 0.2, Vec3::make_vec3(0.5, 0.8, 0.9), 0.6),
+            ),
+            World::make_showcase(0.0, Mat::Disp(Dispersive::make_flint())),
+            World::make_showcase(0.9, Mat::make_mat_blackbody(2700.0, 2.0)),
+            World::make_showcase(
+                1.8,
+                Mat::Prin(Principled {
+                    roughness: 0.4,
+                    clearcoat: 1.0,
+                    sheen: 0.5,
+                    ..Principled::make_principled(Vec3::make_vec3(0.6, 0.15, 0.1))
+                }),
+            ),
+            World::make_showcase(
+                2.7,
+                Mat::make_mat_coated(
+                    Mat::make_mat_lmb(0.5, 0.7, 0.6),
+                    1.5,
+                    0.1,
+                    Vec3::make_vec3(0.2, 0.4, 0.8),
+                    0.2,
+                ),
+            ),
+            Entity::Cyl(Cylinder::make_cylinder(
+                origin + Vec3::make_vec3(-1.15, -0.3, -1.25),
+                Vec3::make_vec3(0.0, 1.0, 0.0),
+                0.15,
+                0.35,
+                true,
+                Mat::Cond(Conductor::make_aluminium(0.2)),
+            )),
+            Entity::Con(Cone::make_cone(
+                origin + Vec3::make_vec3(1.5, -0.3, -1.1),
+                Vec3::make_vec3(0.0, 1.0, 0.0),
+                0.18,
+                0.4,
+                true,
+                Mat::make_mat_principled(Vec3::make_vec3(0.1, 0.25, 0.6)),
+            )),
+            Entity::Tor(Torus::make_torus(
+                origin + Vec3::make_vec3(1.05, -0.14, -0.8),
+                Vec3::make_vec3(0.4, 0.3, 1.0),
+                0.12,
+                0.04,
+                Mat::Cond(Conductor::make_copper(0.2)),
+            )),
+            Entity::Cap(Capsule::make_capsule(
+                origin + Vec3::make_vec3(-1.0, -0.25, -0.85),
+                origin + Vec3::make_vec3(-0.7, -0.25, -1.0),
+                0.05,
+                Mat::make_mat_lmb(0.8, 0.8, 0.75),
+            )),
+            Entity::Imp(Implicit::make_implicit(
+                Sdf::make_translate(
+                    Vec3::make_vec3(-0.1, 0.75, -1.5),
+                    Sdf::make_scale(0.18, Sdf::Mandelbulb(8.0, 8)),
+                ),
+                Aabb::make_aabb(
+                    origin + Vec3::make_vec3(-0.33, 0.52, -1.73),
+                    origin + Vec3::make_vec3(0.13, 0.98, -1.27),
+                ),
+                1.0,
+                Mat::make_mat_principled(Vec3::make_vec3(0.8, 0.6, 0.3)),
+            )),
+            Entity::Imp(Implicit::make_implicit(
+                Sdf::make_translate(
+                    Vec3::make_vec3(4.2, 0.15, -2.7),
+                    Sdf::make_smooth_union(
+                        Sdf::make_twist(
+                            4.0,
+                            Sdf::make_subtract(
+                                Sdf::RoundBox(Vec3::make_vec3(0.12, 0.45, 0.12), 0.02),
+                                Sdf::make_repeat(
+                                    Vec3::make_vec3(0.0, 0.15, 0.0),
+                                    Sdf::make_translate(
+                                        Vec3::make_vec3(0.12, 0.0, 0.12),
+                                        Sdf::Sphere(0.06),
+                                    ),
+                                ),
+                            ),
+                        ),
+                        Sdf::make_translate(
+                            Vec3::make_vec3(0.0, 0.45, 0.0),
+                            Sdf::Torus(0.15, 0.04),
+                        ),
+                        0.05,
+                    ),
+                ),
+                Aabb::make_aabb(
+                    origin + Vec3::make_vec3(3.95, -0.3, -2.95),
+                    origin + Vec3::make_vec3(4.45, 0.7, -2.45),
+                ),
+                0.6,
+                Mat::make_mat_lmb(0.7, 0.3, 0.4),
+            )),
+            Entity::Csg(Csg::make_csg(
+                // A lens, between two spheres.
+                CsgOp::Intersect,
+                Entity::Sph(Sphere::make_sphere(
+                    origin + Vec3::make_vec3(0.35, 0.35, -0.44),
+                    0.3,
+                    Mat::make_mat_detc(1.5),
+                )),
+                Entity::Sph(Sphere::make_sphere(
+                    origin + Vec3::make_vec3(0.35, 0.35, -0.96),
+                    0.3,
+                    Mat::make_mat_detc(1.5),
+                )),
+                Mat::make_mat_detc(1.5),
+            )),
+            Entity::Csg(Csg::make_csg(
+                // A bead, drilled through.
+                CsgOp::Subtract,
+                Entity::Sph(Sphere::make_sphere(
+                    origin + Vec3::make_vec3(-0.62, -0.2, -0.5),
+                    0.1,
+                    Mat::make_mat_lmb(0.5, 0.5, 0.5),
+                )),
+                Entity::Cyl(Cylinder::make_cylinder(
+                    origin + Vec3::make_vec3(-0.65, -0.24, -0.6),
+                    Vec3::make_vec3(0.3, 0.4, 1.0),
+                    0.04,
+                    0.3,
+                    true,
+                    Mat::make_mat_lmb(0.5, 0.5, 0.5),
+                )),
+                Mat::make_mat_principled(Vec3::make_vec3(0.9, 0.85, 0.8)),
+            )),
+            Entity::Bez(World::make_patches(
+                origin + Vec3::make_vec3(0.65, -0.3, -0.75),
+            )),
+            Entity::Dsk(Disk::make_disk(
+                origin + Vec3::make_vec3(-1.3, 0.1, -1.5),
+                Vec3::make_vec3(0.4, 0.0, 1.0),
+                0.4,
+                Mat::make_mat_cutout(Mat::make_mat_lmb(0.2, 0.5, 0.15), Opacity::Noise(4.0)),
+            )),
+            Entity::Vol(Volume::make_volume(
+                Aabb::make_aabb(
+                    origin + Vec3::make_vec3(0.2, 0.35, -1.6),
+                    origin + Vec3::make_vec3(1.2, 0.85, -1.0),
+                ),
+                World::make_grid(),
+                VOLUME_DENSITY,
+                Mat::make_mat_medium(Vec3::make_vec3(0.95, 0.95, 0.95), Phase::make_hg(0.6)),
+            )),
         ];
-        World { obj_list: new_list }
+        let light_list: Vec<Light> = vec![Light::make_light_spot(
+            origin + Vec3::make_vec3(-1.0, 1.5, 0.0),
+            Vec3::make_vec3(1.0, -1.5, -1.0),
+            Vec3::make_vec3(5.0, 4.5, 4.0),
+            15.0,
+            25.0,
+        )];
+        World {
+            light_tree: LightTree::make_tree(&new_list, &light_list),
+            obj_list: new_list,
+            background: World::make_background(),
+            light_list,
+        }
     }
 
-    fn do_trace(&self, target_ray: &Ray, depth: i32) -> Vec3 {
-        if depth <= 0 {
-            return Vec3::make_vec3(0.0, 0.0, 0.0);
+    fn make_showcase(x: f64, material: Mat) -> Entity {
+        Entity::Sph(Sphere::make_sphere(
+            origin + Vec3::make_vec3(x, 1.55, -1.8),
+            0.15,
+            material,
+        ))
+    }
+
+    fn make_background() -> Background {
+        if ENV_MAP_PATH.is_empty() {
+            return if USE_SKY_MODEL {
+                Background::Sky(SkyModel::make_sky(
+                    SUN_ELEVATION,
+                    SUN_AZIMUTH,
+                    TURBIDITY,
+                    SKY_EXPOSURE,
+                ))
+            } else {
+                Background::Gradient
+            };
+        }
+        match EnvMap::make_env_map(ENV_MAP_PATH, ENV_MAP_ROTATION, ENV_MAP_INTENSITY) {
+            Ok(env) => Background::Env(env),
+            Err(err) => {
+                println!("Loading environment map fails: {}", err);
+                Background::Gradient
+            }
         }
-        let mut target_obj = &(Entity::make_none_entity());
+    }
+
+    fn make_grid() -> Grid {
+        if VOLUME_GRID_PATH.is_empty() {
+            return Grid::make_cloud(48);
+        }
+        match Grid::load(VOLUME_GRID_PATH) {
+            Ok(grid) => grid,
+            Err(err) => {
+                println!("Loading voxel grid fails: {}", err);
+                Grid::make_cloud(48)
+            }
+        }
+    }
+
+    fn make_patches(pos: Vec3) -> BezierMesh {
+        // Glazed, standing at pos.
+        let glaze = || {
+            Mat::make_mat_coated(
+                Mat::make_mat_lmb(0.1, 0.3, 0.6),
+                1.5,
+                0.05,
+                Vec3::make_vec3(0.0, 0.0, 0.0),
+                0.0,
+            )
+        };
+        // A vase turned from its profile, (radius, height) of two cubic segments.
+        let vase = || {
+            let profile = [
+                (0.06, 0.0),
+                (0.11, 0.03),
+                (0.13, 0.1),
+                (0.07, 0.15),
+                (0.03, 0.19),
+                (0.04, 0.23),
+                (0.06, 0.25),
+            ];
+            BezierMesh::make_revolution(pos, &profile, glaze()).expect("the vase has a profile")
+        };
+        if PATCHES_PATH.is_empty() {
+            return vase();
+        }
+        match BezierMesh::load(PATCHES_PATH, pos, PATCHES_SCALE, glaze()) {
+            Ok(mesh) => mesh,
+            Err(err) => {
+                println!("Loading patches fails: {}", err);
+                vase()
+            }
+        }
+    }
+
+    fn make_floor_map() -> NormalMap {
+        let bumps = NormalMap::make_bump(Height::Noise(6.0), NORMAL_MAP_STRENGTH);
+        if NORMAL_MAP_PATH.is_empty() {
+            return bumps;
+        }
+        match NormalMap::make_tangent(NORMAL_MAP_PATH, NORMAL_MAP_STRENGTH) {
+            Ok(map) => map,
+            Err(err) => {
+                println!("Loading normal map fails: {}", err);
+                bumps
+            }
+        }
+    }
+
+    fn get_hit_time(&self, obj: &Entity, target_ray: &Ray) -> f64 {
+        // Passes through the obj where its mask is transparent, and through
+        // fractional alpha with the probability of the transparency.
+        let material = match obj.get_material() {
+            Some(material) if material.is_cutout() => material,
+            _ => return obj.get_hit_time(target_ray),
+        };
+        let dir = target_ray.get_dir();
+        let mut offset = 0.0;
+        for _ in 0..CUTOUT_MAX_LAYERS {
+            let layer_ray = Ray::make_ray_at(
+                target_ray.get_pos() + offset * dir,
+                dir,
+                target_ray.get_wavelength(),
+            );
+            let tm = obj.get_hit_time(&layer_ray);
+            if tm < EPS {
+                return -1.0;
+            }
+            let uv = obj.get_uv(layer_ray.get_pos() + tm * dir);
+            if rand_0_1() < material.get_opacity(uv) {
+                return offset + tm;
+            }
+            offset += tm;
+        }
+        -1.0
+    }
+
+    fn find_first_hit(&self, target_ray: &Ray) -> Option<(f64, usize)> {
+        // Hit time and index of the obj.
+        let mut target_id = None;
         let mut first_hit_time: f64 = -1.0;
-        for obj in &(self.obj_list) {
-            let tm: f64 = obj.get_hit_time(target_ray);
+        for (id, obj) in self.obj_list.iter().enumerate() {
+            let tm: f64 = self.get_hit_time(obj, target_ray);
             if tm < EPS {
                 continue;
             }
             if first_hit_time < EPS || first_hit_time > tm {
                 first_hit_time = tm;
-                target_obj = obj;
+                target_id = Some(id);
             }
         }
+        target_id.map(|id| (first_hit_time, id))
+    }
+
+    fn get_transmittance(&self, pos: Vec3, dir: Vec3, dist: f64) -> f64 {
+        // Fraction of light reaching dist along dir, 0 if occluded by a surface.
+        let target_ray = Ray::make_ray(pos, dir);
+        let mut res = 1.0;
+        for obj in &self.obj_list {
+            res *= match obj.get_material() {
+                Some(material) if material.is_cutout() => {
+                    let tm = self.get_hit_time(obj, &target_ray);
+                    if tm >= EPS && tm < dist - EPS {
+                        0.0
+                    } else {
+                        1.0
+                    }
+                }
+                _ => obj.get_transmittance(&target_ray, dist - EPS),
+            };
+            if res <= 0.0 {
+                break;
+            }
+        }
+        res
+    }
+
+    fn sample_punctual(
+        &self,
+        pos: Vec3,
+        in_dir: Vec3,
+        normal: Vec3,
+        material: &Mat,
+        id: usize,
+        lambdas: Vec3,
+    ) -> Vec3 {
+        // Unoccluded light from a punctual light, which cannot be hit by bsdf sampling.
+        let (dir, dist, li) = self.light_list[id].sample_li(pos);
+        if li.get_max() <= 0.0 {
+            return Vec3::make_vec3(0.0, 0.0, 0.0);
+        }
+        let f: Vec3 = material.eval(in_dir, dir, normal);
+        if f.get_max() <= 0.0 {
+            return Vec3::make_vec3(0.0, 0.0, 0.0);
+        }
+        let transmittance = self.get_transmittance(pos, dir, dist);
+        // Upsampled apart, the product of the spectra is not that of the rgb.
+        transmittance * rgb_to_spectrum(f, lambdas) * illuminant_to_spectrum(li, lambdas)
+    }
 
-        if first_hit_time < EPS {
-            // Hit nothing, background color.
-            let p: f64 = 0.5 * (target_ray.get_dir().y + 1.0);
-            (1.0 - p) * Vec3::make_vec3(1.0, 1.0, 1.0) + p * Vec3::make_vec3(0.5, 0.7, 1.0)
+    #[allow(clippy::too_many_arguments)]
+    fn sample_emitter(
+        &self,
+        pos: Vec3,
+        in_dir: Vec3,
+        normal: Vec3,
+        material: &Mat,
+        id: usize,
+        select_pdf: f64,
+        lambdas: Vec3,
+    ) -> Vec3 {
+        // Light from a point on an emissive obj, weighted against bsdf sampling.
+        let obj = &self.obj_list[id];
+        let (dir, pdf) = obj.sample_dir_from(pos);
+        if pdf <= 0.0 {
+            return Vec3::make_vec3(0.0, 0.0, 0.0);
+        }
+        let f: Vec3 = material.eval(in_dir, dir, normal);
+        if f.get_max() <= 0.0 {
+            return Vec3::make_vec3(0.0, 0.0, 0.0);
+        }
+        let tm = self.get_hit_time(obj, &Ray::make_ray(pos, dir));
+        let transmittance = if tm < EPS {
+            0.0
         } else {
+            self.get_transmittance(pos, dir, tm)
+        };
+        if transmittance <= 0.0 {
+            return Vec3::make_vec3(0.0, 0.0, 0.0); // Occluded.
+        }
+        let light_normal = obj.get_hit_normal(pos + tm * dir);
+        let le = match obj.get_material() {
+            Some(light_material) => light_material.get_emission(dir, light_normal, lambdas),
+            None => return Vec3::make_vec3(0.0, 0.0, 0.0),
+        };
+        let light_pdf = select_pdf * pdf;
+        let weight = power_heuristic(light_pdf, material.get_pdf(in_dir, dir, normal));
+        (transmittance * weight / light_pdf) * rgb_to_spectrum(f, lambdas) * le
+    }
+
+    fn sample_lights(
+        &self,
+        pos: Vec3,
+        in_dir: Vec3,
+        normal: Vec3,
+        material: &Mat,
+        lambdas: Vec3,
+    ) -> Vec3 {
+        // Lights at infinity are all evaluated, one more light is picked from the light tree.
+        let mut res = Vec3::make_vec3(0.0, 0.0, 0.0);
+        for (id, light) in self.light_list.iter().enumerate() {
+            if light.get_position().is_none() {
+                res = res + self.sample_punctual(pos, in_dir, normal, material, id, lambdas);
+            }
+        }
+
+        if let Some((slot, select_pdf)) = self.light_tree.sample(pos, facing(in_dir, normal)) {
+            res = res
+                + match self.light_tree.get_light(slot) {
+                    LightRef::Punctual(id) => {
+                        self.sample_punctual(pos, in_dir, normal, material, id, lambdas)
+                            / select_pdf
+                    }
+                    LightRef::Emitter(id) => {
+                        self.sample_emitter(pos, in_dir, normal, material, id, select_pdf, lambdas)
+                    }
+                };
+        }
+        res
+    }
+
+    fn sample_background(
+        &self,
+        pos: Vec3,
+        in_dir: Vec3,
+        normal: Vec3,
+        material: &Mat,
+        lambdas: Vec3,
+    ) -> Vec3 {
+        // Direct lighting from the background, weighted against bsdf sampling.
+        let (dir, pdf) = self.background.sample_dir();
+        if pdf <= 0.0 {
+            return Vec3::make_vec3(0.0, 0.0, 0.0);
+        }
+        let f: Vec3 = material.eval(in_dir, dir, normal);
+        if f.get_max() <= 0.0 {
+            return Vec3::make_vec3(0.0, 0.0, 0.0);
+        }
+        let transmittance = self.get_transmittance(pos, dir, f64::INFINITY);
+        if transmittance <= 0.0 {
+            return Vec3::make_vec3(0.0, 0.0, 0.0);
+        }
+        let weight = power_heuristic(pdf, material.get_pdf(in_dir, dir, normal));
+        let radiance = illuminant_to_spectrum(self.background.get_radiance(dir), lambdas);
+        (transmittance * weight / pdf) * rgb_to_spectrum(f, lambdas) * radiance
+    }
+
+    fn do_trace(&self, target_ray: &Ray, lambdas: Vec3) -> (Vec3, (Vec3, Vec3, f64)) {
+        // Rgb, or spectral at lambdas if they are not 0. Also the first-hit albedo, normal
+        // (facing the ray) and depth, which guide the denoiser.
+        let view_dir = target_ray.get_dir();
+        let mut features: Option<(Vec3, Vec3, f64)> = None;
+        let mut target_ray = *target_ray;
+        let mut throughput = Vec3::make_vec3(1.0, 1.0, 1.0);
+        let mut color = Vec3::make_vec3(0.0, 0.0, 0.0);
+        let mut last_pdf: f64 = 0.0; // Pdf of the last bounce, 0 if it is specular.
+        let mut last_pos: Vec3 = target_ray.get_pos();
+        let mut last_normal: Vec3 = target_ray.get_dir();
+        let mut wavelength: f64 = 0.0; // Rgb until the path meets a dispersive material.
+        let mut depth = 0;
+        let mut walk_steps = 0; // Scattering events inside media, which are not bounces.
+
+        while depth < ITERATION_DEPTH {
+            let (first_hit_time, target_id) = match self.find_first_hit(&target_ray) {
+                Some(hit) => hit,
+                None => {
+                    // Hit nothing, background color.
+                    let dir = target_ray.get_dir();
+                    let weight = if last_pdf > 0.0 && self.background.can_sample() {
+                        power_heuristic(last_pdf, self.background.get_pdf(dir))
+                    } else {
+                        1.0
+                    };
+                    let radiance =
+                        illuminant_to_spectrum(self.background.get_radiance(dir), lambdas);
+                    return (
+                        color + weight * throughput * radiance,
+                        features.unwrap_or_else(|| self.get_background_features(view_dir)),
+                    );
+                }
+            };
+
             // To be updated with different materials.
-            let pos: Vec3 = target_ray.get_pos() + first_hit_time * target_ray.get_dir();
-            let normal: Vec3 = target_obj.get_hit_normal(pos);
-            let target_ray = &(Ray::make_ray(pos, target_ray.get_dir()));
-            let target_ray = &(target_obj.scatter(target_ray, normal));
+            let target_obj = &self.obj_list[target_id];
+            let in_dir: Vec3 = target_ray.get_dir();
+            let pos: Vec3 = target_ray.get_pos() + first_hit_time * in_dir;
+            let (normal, uv, tangent) = target_obj.get_surface(pos);
+            let material = match target_obj.get_material() {
+                Some(material) => material,
+                None => break,
+            };
+            if features.is_none() {
+                let normal = material.get_shading_normal(in_dir, normal, tangent, uv);
+                features = Some((
+                    target_obj.get_albedo(),
+                    facing(in_dir, normal),
+                    first_hit_time,
+                ));
+            }
+
+            if let Some((sigma_t, albedo, phase)) = material.get_medium() {
+                if !is_front_face(in_dir, normal) {
+                    // Inside the medium, a random walk. The distance is sampled by one of
+                    // the channels and weighted by the average pdf of all of them.
+                    let sigma_t = rgb_to_spectrum(sigma_t, lambdas);
+                    let channel = ((rand_0_1() * 3.0) as usize).min(2);
+                    let dist = -(1.0 - rand_0_1()).ln() / sigma_t.get(channel);
+                    let transmittance = (origin - dist.min(first_hit_time) * sigma_t).exp();
+                    if dist < first_hit_time {
+                        if walk_steps >= SSS_MAX_STEPS {
+                            break;
+                        }
+                        let pdf =
+                            dot(sigma_t * transmittance, Vec3::make_vec3(1.0, 1.0, 1.0)) / 3.0;
+                        let albedo = rgb_to_spectrum(albedo, lambdas);
+                        throughput = throughput * (albedo * sigma_t * transmittance) / pdf;
+                        target_ray = Ray::make_ray_at(
+                            target_ray.get_pos() + dist * in_dir,
+                            phase.sample(in_dir),
+                            wavelength,
+                        );
+                        last_pdf = 0.0;
+                        walk_steps += 1;
+                        match roulette(throughput) {
+                            Some(survivor) => throughput = survivor,
+                            None => break,
+                        }
+                        continue;
+                    }
+                    let pdf = dot(transmittance, Vec3::make_vec3(1.0, 1.0, 1.0)) / 3.0;
+                    throughput = throughput * transmittance / pdf;
+                }
+            }
+
+            let absorption = material.get_absorption();
+            if absorption.get_max() > 0.0 && !is_front_face(in_dir, normal) {
+                // Travelled inside the medium, attenuated by Beer-Lambert law.
+                let transmittance = (origin - first_hit_time * absorption).exp();
+                throughput = throughput * rgb_to_spectrum(transmittance, lambdas);
+            }
+
+            let emission = material.get_emission(in_dir, normal, lambdas);
+            if emission.get_max() > 0.0 {
+                // Emitters in the light tree are also reached by light sampling.
+                let weight = match self.light_tree.get_slot_of_obj(target_id) {
+                    Some(slot) if last_pdf > 0.0 => {
+                        let light_pdf = self.light_tree.get_pdf(last_pos, last_normal, slot)
+                            * target_obj.get_pdf_from(last_pos, in_dir);
+                        power_heuristic(last_pdf, light_pdf)
+                    }
+                    _ => 1.0,
+                };
+                color = color + weight * throughput * emission;
+            }
+            if !material.does_scatter() {
+                break;
+            }
+            // Shading normal, which the geometric normal above only decides the side of.
+            let normal = material.get_shading_normal(in_dir, normal, tangent, uv);
+
+            if !material.is_specular() {
+                color =
+                    color + throughput * self.sample_lights(pos, in_dir, normal, material, lambdas);
+                if self.background.can_sample() {
+                    color = color
+                        + throughput
+                            * self.sample_background(pos, in_dir, normal, material, lambdas);
+                }
+            }
 
-            //return crate::DEFAULT_COLOR;
-            (target_obj.get_albedo()) * self.do_trace(target_ray, depth - 1)
+            if material.is_dispersive() && wavelength <= 0.0 {
+                // From now on the path carries a single wavelength.
+                if lambdas.x > 0.0 {
+                    // The hero wavelength, the other two are terminated.
+                    wavelength = lambdas.x;
+                    throughput = Vec3::make_vec3(3.0 * throughput.x, 0.0, 0.0);
+                } else {
+                    let (lambda, pdf) = sample_wavelength();
+                    wavelength = lambda;
+                    throughput = throughput * get_wavelength_weight(lambda, pdf);
+                }
+            }
+
+            target_ray = material.scatter(&Ray::make_ray_at(pos, in_dir, wavelength), normal);
+            last_pdf = if material.is_specular() {
+                0.0
+            } else {
+                material.get_pdf(in_dir, target_ray.get_dir(), normal)
+            };
+            last_pos = pos;
+            last_normal = facing(in_dir, normal);
+            let weight = material.get_weight(in_dir, target_ray.get_dir(), normal);
+            throughput = throughput * rgb_to_spectrum(weight, lambdas);
+            depth += 1;
+
+            if depth >= RR_MIN_DEPTH {
+                match roulette(throughput) {
+                    Some(survivor) => throughput = survivor,
+                    None => break,
+                }
+            }
         }
+
+        (
+            color,
+            features.unwrap_or_else(|| self.get_background_features(view_dir)),
+        )
+    }
+
+    fn get_background_features(&self, dir: Vec3) -> (Vec3, Vec3, f64) {
+        (
+            self.background.get_radiance(dir),
+            Vec3::make_vec3(0.0, 0.0, 0.0),
+            0.0,
+        )
     }
 
     // Here comes the most important function that actually do the tracing process of target ray.
-    pub fn trace_ray_color(&self, target_ray: &Ray) -> Vec3 {
-        self.do_trace(target_ray, ITERATION_DEPTH)
+    // The features of its first hit come along, see do_trace.
+    pub fn trace_ray_color(&self, target_ray: &Ray) -> (Vec3, (Vec3, Vec3, f64)) {
+        self.do_trace(target_ray, Vec3::make_vec3(0.0, 0.0, 0.0))
+    }
+
+    // Radiance at 3 wavelengths sampled by hero wavelength sampling, the wavelengths, and
+    // the features of the first hit.
+    pub fn trace_ray_spectrum(&self, target_ray: &Ray) -> (Vec3, Vec3, (Vec3, Vec3, f64)) {
+        let lambdas = sample_hero_wavelengths();
+        let (values, features) = self.do_trace(target_ray, lambdas);
+        (values, lambdas, features)
+    }
+}
+
+fn facing(in_dir: Vec3, normal: Vec3) -> Vec3 {
+    // The normal on the side the ray comes from.
+    if dot(in_dir, normal) > 0.0 {
+        origin - normal
+    } else {
+        normal
+    }
+}
+
+fn roulette(throughput: Vec3) -> Option<Vec3> {
+    // Russian roulette: survive with the probability of the throughput,
+    // and compensate the survivors so that the estimate stays unbiased.
+    let survive: f64 = throughput.get_max().min(1.0);
+    if rand_0_1() >= survive {
+        None
+    } else {
+        Some(throughput / survive)
+    }
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    // Multiple importance sampling weight of the strategy with pdf.
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b <= 0.0 {
+        0.0
+    } else {
+        a / (a + b)
     }
 }